@@ -0,0 +1,37 @@
+use beanstalkrs::queue::Queue;
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tokio::{runtime::Runtime, sync::mpsc};
+
+fn filled_queue(size: u64) -> Queue {
+    let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+    let mut queue = Queue::new(ready_job_tx);
+    for i in 0..size {
+        queue.new_job("default".to_string(), 60, (i % 1024) as u32, Bytes::new());
+    }
+    queue
+}
+
+/// Time per put/reserve/delete cycle should stay roughly flat as the number of jobs grows.
+fn put_reserve_delete(c: &mut Criterion) {
+    // `Queue::new` spawns its delay watcher, so it needs a runtime to run in
+    let rt = Runtime::new().unwrap();
+    let _guard = rt.enter();
+
+    let mut group = c.benchmark_group("put-reserve-delete");
+    for size in [1_000, 10_000, 100_000, 1_000_000] {
+        let mut queue = filled_queue(size);
+        let watch_list = vec!["default".to_string()];
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| {
+                queue.new_job("default".to_string(), 60, 512, Bytes::new());
+                let id = queue.reserve_job(watch_list.clone(), 1).unwrap().id;
+                queue.delete_job(id, 1).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, put_reserve_delete);
+criterion_main!(benches);
@@ -13,6 +13,16 @@ pub fn parse(input: TokenStream) -> TokenStream {
     } else {
         panic!("Must be an enum");
     };
+    let name_arms: Vec<_> = variants
+        .iter()
+        .map(|variant| {
+            let var_ident = &variant.ident;
+            let cmd_name = Lit::Str(LitStr::new(&camel_case(&var_ident)[..], variant.span()));
+            quote! {
+                Self::#var_ident { .. } => #cmd_name
+            }
+        })
+        .collect();
     let match_arms: Vec<_> = variants
         .iter()
         .map(|variant| {
@@ -69,6 +79,15 @@ pub fn parse(input: TokenStream) -> TokenStream {
                 Ok(cmd)
             }
         }
+
+        impl #struct_ident {
+            /// The name of the command as it appears on the wire
+            pub fn name(&self) -> &'static str {
+                match self {
+                    #(#name_arms,)*
+                }
+            }
+        }
     };
 
     TokenStream::from(expanded)
@@ -100,7 +119,7 @@ impl ValidType {
         if let Type::Path(TypePath { path, .. }) = &field.ty {
             let ty_ident = &path.segments.last()?.ident;
             match &ty_ident.to_string()[..] {
-                "u32" => Some(Self::Integer),
+                "u32" | "u64" => Some(Self::Integer),
                 "String" => Some(Self::String),
                 "Bytes" => Some(Self::Bytes),
                 _ => None,
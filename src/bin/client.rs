@@ -6,7 +6,7 @@ use tokio::{
 #[tokio::main]
 async fn main() {
     // TODO: this is ugly code
-    let mut stream = TcpStream::connect("127.0.0.1:3000").await.unwrap();
+    let mut stream = TcpStream::connect("127.0.0.1:11300").await.unwrap();
     stream.write_all(b"put 1 1 1 1\r\nh\r\n").await.unwrap();
 
     let mut buffer = [0; 1024];
@@ -0,0 +1,17 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::Mutex;
+
+use crate::{codec::Data, connection::Connection, queue::Queue};
+
+pub async fn bury(
+    connection: &mut Connection,
+    queue: Arc<Mutex<Queue>>,
+    id: u64,
+    pri: u32,
+) -> Result<Vec<Data>> {
+    queue.lock().await.bury_job(id, connection.id(), pri)?;
+    connection.remove_reserved(id).await;
+    Ok(vec![Data::String("BURIED".into())])
+}
@@ -8,13 +8,9 @@ use crate::{codec::Data, connection::Connection, queue::Queue};
 pub async fn delete(
     connection: &mut Connection,
     queue: Arc<Mutex<Queue>>,
-    id: u32,
+    id: u64,
 ) -> Result<Vec<Data>> {
+    queue.lock().await.delete_job(id, connection.id())?;
     connection.remove_reserved(id).await;
-    let mut queue = queue.lock().await;
-    if queue.delete_job(id) {
-        Ok(vec![Data::String("DELETED".into())])
-    } else {
-        Ok(vec![Data::String("NOT_FOUND".into())])
-    }
+    Ok(vec![Data::String("DELETED".into())])
 }
@@ -1,16 +1,26 @@
+use std::sync::Arc;
+
 use anyhow::Result;
+use tokio::sync::Mutex;
 
-use crate::{codec::Data, connection::Connection};
+use crate::{codec::Data, connection::Connection, queue::Queue};
 
-pub fn ignore(connection: &mut Connection, tube: String) -> Result<Vec<Data>> {
+pub async fn ignore(
+    connection: &mut Connection,
+    queue: Arc<Mutex<Queue>>,
+    tube: String,
+) -> Result<Vec<Data>> {
     let watched_tubes = connection.get_watched_tubes();
     if watched_tubes.len() == 1 && watched_tubes.contains(&tube) {
         Ok(vec![Data::String("NOT_IGNORED".into())])
     } else {
-        connection.ignore(tube);
+        let mut queue = queue.lock().await;
+        if connection.ignore(tube.clone()) {
+            queue.ignore_tube(&tube);
+        }
         Ok(vec![
             Data::String("WATCHING".into()),
-            Data::Integer(connection.get_watched_tubes().len() as u32),
+            Data::Integer(connection.get_watched_tubes().len() as u64),
         ])
     }
 }
@@ -0,0 +1,25 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::Mutex;
+
+use crate::{codec::Data, connection::Connection, queue::Queue};
+
+pub async fn kick(
+    connection: &mut Connection,
+    queue: Arc<Mutex<Queue>>,
+    bound: u32,
+) -> Result<Vec<Data>> {
+    let mut queue = queue.lock().await;
+    let count = queue.kick(connection.tube(), bound);
+    Ok(vec![
+        Data::String("KICKED".into()),
+        Data::Integer(count as u64),
+    ])
+}
+
+pub async fn kick_job(queue: Arc<Mutex<Queue>>, id: u64) -> Result<Vec<Data>> {
+    let mut queue = queue.lock().await;
+    queue.kick_job(id)?;
+    Ok(vec![Data::String("KICKED".into())])
+}
@@ -17,7 +17,7 @@ pub async fn list_tubes(queue: Arc<Mutex<Queue>>) -> Result<Vec<Data>> {
     );
     Ok(vec![
         Data::String("OK".into()),
-        Data::Integer(body.len() as u32),
+        Data::Integer(body.len() as u64),
         Data::Crlf,
         Data::Bytes(Bytes::copy_from_slice(body.as_bytes())),
     ])
@@ -14,7 +14,7 @@ pub async fn list_tubes_watched(connection: &mut Connection) -> Result<Vec<Data>
     );
     Ok(vec![
         Data::String("OK".into()),
-        Data::Integer(body.len() as u32),
+        Data::Integer(body.len() as u64),
         Data::Crlf,
         Data::Bytes(Bytes::copy_from_slice(body.as_bytes())),
     ])
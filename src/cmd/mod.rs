@@ -7,14 +7,23 @@ use tokio::sync::Mutex;
 
 use crate::{codec::Data, connection::Connection, queue::Queue};
 
+mod bury;
 mod delete;
 mod ignore;
+mod kick;
 mod list_tube_used;
 mod list_tubes;
 mod list_tubes_watched;
+mod pause_tube;
+mod peek;
 mod put;
 mod quit;
+mod release;
 mod reserve;
+mod stats;
+mod stats_job;
+mod stats_tube;
+mod touch;
 mod r#use;
 mod watch;
 
@@ -35,22 +44,22 @@ pub enum Cmd {
         seconds: u32,
     },
     ReserveJob {
-        id: u32,
+        id: u64,
     },
     Delete {
-        id: u32,
+        id: u64,
     },
     Release {
-        id: u32,
+        id: u64,
         pri: u32,
         delay: u32,
     },
     Bury {
-        id: u32,
+        id: u64,
         pri: u32,
     },
     Touch {
-        id: u32,
+        id: u64,
     },
     Watch {
         tube: String,
@@ -59,7 +68,7 @@ pub enum Cmd {
         tube: String,
     },
     Peek {
-        id: u32,
+        id: u64,
     },
     PeekReady,
     PeekDelayed,
@@ -68,10 +77,10 @@ pub enum Cmd {
         bound: u32,
     },
     KickJob {
-        id: u32,
+        id: u64,
     },
     StatsJob {
-        id: u32,
+        id: u64,
     },
     StatsTube {
         tube: String,
@@ -94,6 +103,20 @@ impl Cmd {
         connection: &mut Connection,
         queue: Arc<Mutex<Queue>>,
     ) -> Result<Vec<Data>> {
+        {
+            let mut queue = queue.lock().await;
+            queue.stats.count_command(self.name());
+            if matches!(self, Cmd::Put { .. }) && connection.make_producer() {
+                queue.stats.current_producers += 1;
+            }
+            if matches!(
+                self,
+                Cmd::Reserve | Cmd::ReserveWithTimeout { .. } | Cmd::ReserveJob { .. }
+            ) && connection.make_worker()
+            {
+                queue.stats.current_workers += 1;
+            }
+        }
         match self {
             Cmd::Put {
                 pri,
@@ -103,31 +126,35 @@ impl Cmd {
                 data,
             } => put::put(connection, queue, pri, delay, ttr, data).await,
             Cmd::Use { tube } => r#use::use_tube(connection, queue, tube).await,
-            Cmd::Reserve => reserve::reserve_with_timeout(connection, queue, 0).await,
+            Cmd::Reserve => reserve::reserve(connection, queue, None).await,
             Cmd::ReserveWithTimeout { seconds } => {
-                reserve::reserve_with_timeout(connection, queue, seconds).await
+                reserve::reserve(connection, queue, Some(seconds)).await
             }
             Cmd::ReserveJob { id } => reserve::reserve_job(connection, queue, id).await,
             Cmd::Delete { id } => delete::delete(connection, queue, id).await,
-            Cmd::Release { id, pri, delay } => todo!(),
-            Cmd::Bury { id, pri } => todo!(),
-            Cmd::Touch { id } => todo!(),
-            Cmd::Watch { tube } => watch::watch(connection, tube),
-            Cmd::Ignore { tube } => ignore::ignore(connection, tube),
-            Cmd::Peek { id } => todo!(),
-            Cmd::PeekReady => todo!(),
-            Cmd::PeekDelayed => todo!(),
-            Cmd::PeekBuried => todo!(),
-            Cmd::Kick { bound } => todo!(),
-            Cmd::KickJob { id } => todo!(),
-            Cmd::StatsJob { id } => todo!(),
-            Cmd::StatsTube { tube } => todo!(),
-            Cmd::Stats => todo!(),
+            Cmd::Release { id, pri, delay } => {
+                release::release(connection, queue, id, pri, delay).await
+            }
+            Cmd::Bury { id, pri } => bury::bury(connection, queue, id, pri).await,
+            Cmd::Touch { id } => touch::touch(connection, queue, id).await,
+            Cmd::Watch { tube } => watch::watch(connection, queue, tube).await,
+            Cmd::Ignore { tube } => ignore::ignore(connection, queue, tube).await,
+            Cmd::Peek { id } => peek::peek(queue, id).await,
+            Cmd::PeekReady => peek::peek_ready(connection, queue).await,
+            Cmd::PeekDelayed => peek::peek_delayed(connection, queue).await,
+            Cmd::PeekBuried => peek::peek_buried(connection, queue).await,
+            Cmd::Kick { bound } => kick::kick(connection, queue, bound).await,
+            Cmd::KickJob { id } => kick::kick_job(queue, id).await,
+            Cmd::StatsJob { id } => stats_job::stats_job(queue, id).await,
+            Cmd::StatsTube { tube } => stats_tube::stats_tube(queue, tube).await,
+            Cmd::Stats => stats::stats(queue).await,
             Cmd::ListTubes => list_tubes::list_tubes(queue).await,
             Cmd::ListTubeUsed => list_tube_used::list_tube_used(connection).await,
             Cmd::ListTubesWatched => list_tubes_watched::list_tubes_watched(connection).await,
             Cmd::Quit => quit::quit(connection),
-            Cmd::PauseTube { tube_name, delay } => todo!(),
+            Cmd::PauseTube { tube_name, delay } => {
+                pause_tube::pause_tube(queue, tube_name, delay).await
+            }
         }
     }
 }
@@ -159,4 +186,42 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn parse_integer_width() {
+        let data = vec![Data::String("delete".into()), Data::Integer(1 << 32)];
+        assert_eq!(Cmd::try_from(data).unwrap(), Cmd::Delete { id: 1 << 32 });
+
+        let data = vec![
+            Data::String("release".into()),
+            Data::Integer(1),
+            Data::Integer(1 << 32),
+            Data::Integer(0),
+        ];
+        assert_eq!(Cmd::try_from(data).unwrap_err().to_string(), "BAD_FORMAT");
+    }
+
+    #[test]
+    fn name() {
+        assert_eq!(
+            Cmd::Put {
+                pri: 1,
+                delay: 2,
+                ttr: 3,
+                bytes: 4,
+                data: Bytes::new(),
+            }
+            .name(),
+            "put"
+        );
+        assert_eq!(Cmd::ListTubesWatched.name(), "list-tubes-watched");
+        assert_eq!(
+            Cmd::PauseTube {
+                tube_name: "default".into(),
+                delay: 0
+            }
+            .name(),
+            "pause-tube"
+        );
+    }
 }
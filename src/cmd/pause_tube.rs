@@ -0,0 +1,20 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::{
+    sync::Mutex,
+    time::{sleep, Duration},
+};
+
+use crate::{codec::Data, queue::Queue};
+
+pub async fn pause_tube(queue: Arc<Mutex<Queue>>, tube: String, delay: u32) -> Result<Vec<Data>> {
+    if !queue.lock().await.pause_tube(&tube, delay) {
+        return Ok(vec![Data::String("NOT_FOUND".into())]);
+    }
+    tokio::spawn(async move {
+        sleep(Duration::from_secs(delay as u64)).await;
+        queue.lock().await.unpause_tube(&tube);
+    });
+    Ok(vec![Data::String("PAUSED".into())])
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::Mutex;
+
+use crate::{
+    codec::Data,
+    connection::Connection,
+    queue::{Job, Queue},
+};
+
+pub async fn peek(queue: Arc<Mutex<Queue>>, id: u64) -> Result<Vec<Data>> {
+    let queue = queue.lock().await;
+    Ok(found(queue.peek(id)))
+}
+
+pub async fn peek_ready(
+    connection: &mut Connection,
+    queue: Arc<Mutex<Queue>>,
+) -> Result<Vec<Data>> {
+    let queue = queue.lock().await;
+    Ok(found(queue.peek_ready(connection.tube())))
+}
+
+pub async fn peek_delayed(
+    connection: &mut Connection,
+    queue: Arc<Mutex<Queue>>,
+) -> Result<Vec<Data>> {
+    let queue = queue.lock().await;
+    Ok(found(queue.peek_delayed(connection.tube())))
+}
+
+pub async fn peek_buried(
+    connection: &mut Connection,
+    queue: Arc<Mutex<Queue>>,
+) -> Result<Vec<Data>> {
+    let queue = queue.lock().await;
+    Ok(found(queue.peek_buried(connection.tube())))
+}
+
+fn found(job: Option<&Job>) -> Vec<Data> {
+    if let Some(job) = job {
+        vec![
+            Data::String("FOUND".into()),
+            Data::Integer(job.id),
+            Data::Integer(job.data.len() as u64),
+            Data::Crlf,
+            Data::Bytes(job.data.clone()),
+        ]
+    } else {
+        vec![Data::String("NOT_FOUND".into())]
+    }
+}
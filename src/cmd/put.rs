@@ -16,9 +16,7 @@ pub async fn put(
 ) -> Result<Vec<Data>> {
     let mut queue = queue.lock().await;
     let id = if delay > 0 {
-        queue
-            .new_delayed_job(connection.tube().to_string(), ttr, pri, delay, data)
-            .await
+        queue.new_delayed_job(connection.tube().to_string(), ttr, pri, delay, data)
     } else {
         queue.new_job(connection.tube().to_string(), ttr, pri, data)
     };
@@ -0,0 +1,21 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::Mutex;
+
+use crate::{codec::Data, connection::Connection, queue::Queue};
+
+pub async fn release(
+    connection: &mut Connection,
+    queue: Arc<Mutex<Queue>>,
+    id: u64,
+    pri: u32,
+    delay: u32,
+) -> Result<Vec<Data>> {
+    queue
+        .lock()
+        .await
+        .release_job(id, connection.id(), pri, delay)?;
+    connection.remove_reserved(id).await;
+    Ok(vec![Data::String("RELEASED".into())])
+}
@@ -1,10 +1,10 @@
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use tokio::{
     select,
     sync::Mutex,
-    time::{sleep, Duration},
+    time::{sleep, sleep_until, Duration, Instant},
 };
 
 use crate::{
@@ -13,60 +13,74 @@ use crate::{
     queue::{Job, Queue},
 };
 
-pub async fn reserve_with_timeout(
+/// Reserves a job from one of the watched tubes, waiting up to `timeout` seconds for one to
+/// become ready, or forever if there is no timeout.
+pub async fn reserve(
     connection: &mut Connection,
     queue: Arc<Mutex<Queue>>,
-    seconds: u32,
+    timeout: Option<u32>,
 ) -> Result<Vec<Data>> {
     let watched_tubes = connection.get_watched_tubes().to_vec();
-    let timer = sleep(Duration::from_secs(seconds as u64));
-    tokio::pin!(timer);
-    loop {
-        let try_reserve = try_reserve(queue.clone(), watched_tubes.clone());
-        tokio::pin!(try_reserve);
-        select! {
-            _ = &mut timer => {
-                return Ok(vec![Data::String("TIMED_OUT".into())]);
-            }
-            res = &mut try_reserve => {
-                if let Some(job) = res {
-                    connection.add_reserved(job.id, job.ttr).await;
-                    return Ok(vec![
-                        Data::String("RESERVED".into()),
-                        Data::Integer(job.id),
-                        Data::Integer(job.data.len() as u32),
-                        Data::Crlf,
-                        Data::Bytes(job.data.clone()),
-                    ]);
-                } else {
-                    continue;
-                }
-            }
+    let mut job_rx = {
+        let mut queue = queue.lock().await;
+        if let Some(job) = queue.reserve_job(watched_tubes.clone(), connection.id()) {
+            let job = job.clone();
+            drop(queue);
+            return Ok(reserved(connection, job).await);
         }
+        if connection.deadline_soon() {
+            return Ok(vec![Data::String("DEADLINE_SOON".into())]);
+        }
+        if timeout == Some(0) {
+            return Ok(vec![Data::String("TIMED_OUT".into())]);
+        }
+        queue.add_waiter(watched_tubes, connection.id())
+    };
+    let margin_at = connection.safety_margin_at();
+    let response = select! {
+        Ok(job) = &mut job_rx => return Ok(reserved(connection, job).await),
+        _ = sleep(Duration::from_secs(timeout.unwrap_or_default() as u64)), if timeout.is_some() => {
+            Some("TIMED_OUT")
+        }
+        _ = sleep_until(margin_at.unwrap_or_else(Instant::now)), if margin_at.is_some() => {
+            Some("DEADLINE_SOON")
+        }
+        // There's nobody left to reply to, so stop waiting and let `run` see the disconnect
+        _ = connection.closed() => None,
+    };
+    if queue.lock().await.remove_waiter(connection.id()) {
+        return Ok(response
+            .map(|response| vec![Data::String(response.into())])
+            .unwrap_or_default());
     }
-}
-
-async fn try_reserve(queue: Arc<Mutex<Queue>>, watch_list: Vec<String>) -> Option<Job> {
-    let mut queue = queue.lock().await;
-    queue.reserve_job(watch_list).cloned()
+    // A job was handed over between the timer firing and taking the lock
+    let job = job_rx.await.map_err(|_| anyhow!("INTERNAL_ERROR"))?;
+    Ok(reserved(connection, job).await)
 }
 
 pub async fn reserve_job(
     connection: &mut Connection,
     queue: Arc<Mutex<Queue>>,
-    id: u32,
+    id: u64,
 ) -> Result<Vec<Data>> {
-    let mut queue = queue.lock().await;
-    if let Some(job) = queue.reserve_by_id(id) {
-        connection.add_reserved(job.id, job.ttr).await;
-        Ok(vec![
-            Data::String("RESERVED".into()),
-            Data::Integer(job.id),
-            Data::Integer(job.data.len() as u32),
-            Data::Crlf,
-            Data::Bytes(job.data.clone()),
-        ])
-    } else {
-        Ok(vec![Data::String("NOT_FOUND".into())])
+    let job = queue
+        .lock()
+        .await
+        .reserve_by_id(id, connection.id())
+        .cloned();
+    match job {
+        Some(job) => Ok(reserved(connection, job).await),
+        None => Ok(vec![Data::String("NOT_FOUND".into())]),
     }
 }
+
+async fn reserved(connection: &mut Connection, job: Job) -> Vec<Data> {
+    connection.add_reserved(job.id, job.ttr).await;
+    vec![
+        Data::String("RESERVED".into()),
+        Data::Integer(job.id),
+        Data::Integer(job.data.len() as u64),
+        Data::Crlf,
+        Data::Bytes(job.data),
+    ]
+}
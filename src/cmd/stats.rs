@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use bytes::Bytes;
+use tokio::sync::Mutex;
+
+use crate::{codec::Data, queue::Queue, stats};
+
+/// Commands whose counters are reported, in the order beanstalkd lists them
+const COMMANDS: &[&str] = &[
+    "put",
+    "peek",
+    "peek-ready",
+    "peek-delayed",
+    "peek-buried",
+    "reserve",
+    "reserve-with-timeout",
+    "reserve-job",
+    "delete",
+    "release",
+    "use",
+    "watch",
+    "ignore",
+    "bury",
+    "kick",
+    "touch",
+    "stats",
+    "stats-job",
+    "stats-tube",
+    "list-tubes",
+    "list-tube-used",
+    "list-tubes-watched",
+    "pause-tube",
+];
+
+pub async fn stats(queue: Arc<Mutex<Queue>>) -> Result<Vec<Data>> {
+    let queue = queue.lock().await;
+    let (mut urgent, mut ready, mut reserved, mut delayed, mut buried) = (0, 0, 0, 0, 0);
+    let mut tubes = 0;
+    for name in queue.tube_names() {
+        let tube = queue.tube_stats(name).unwrap();
+        urgent += tube.urgent;
+        ready += tube.ready;
+        reserved += tube.reserved;
+        delayed += tube.delayed;
+        buried += tube.buried;
+        tubes += 1;
+    }
+    let stats = &queue.stats;
+    let (utime, stime) = stats::rusage();
+    let body = format!(
+        "---\n\
+         current-jobs-urgent: {urgent}\n\
+         current-jobs-ready: {ready}\n\
+         current-jobs-reserved: {reserved}\n\
+         current-jobs-delayed: {delayed}\n\
+         current-jobs-buried: {buried}\n\
+         {}\
+         job-timeouts: {}\n\
+         total-jobs: {}\n\
+         max-job-size: {}\n\
+         current-tubes: {tubes}\n\
+         current-connections: {}\n\
+         current-producers: {}\n\
+         current-workers: {}\n\
+         current-waiting: {}\n\
+         total-connections: {}\n\
+         pid: {}\n\
+         version: \"{}\"\n\
+         rusage-utime: {}.{:06}\n\
+         rusage-stime: {}.{:06}\n\
+         uptime: {}\n\
+         binlog-oldest-index: 0\n\
+         binlog-current-index: 0\n\
+         binlog-records-migrated: 0\n\
+         binlog-records-written: 0\n\
+         binlog-max-size: {}\n\
+         draining: false\n\
+         id: {}\n\
+         hostname: {}\n\
+         os: {}\n\
+         platform: {}\n",
+        COMMANDS
+            .iter()
+            .map(|name| format!("cmd-{name}: {}\n", stats.commands(name)))
+            .collect::<String>(),
+        stats.job_timeouts,
+        stats.total_jobs,
+        stats.max_job_size,
+        stats.current_connections,
+        stats.current_producers,
+        stats.current_workers,
+        stats.current_waiting,
+        stats.total_connections,
+        std::process::id(),
+        env!("CARGO_PKG_VERSION"),
+        utime.as_secs(),
+        utime.subsec_micros(),
+        stime.as_secs(),
+        stime.subsec_micros(),
+        stats.uptime(),
+        stats.binlog_max_size,
+        stats.id(),
+        stats.hostname(),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+    );
+    Ok(vec![
+        Data::String("OK".into()),
+        Data::Integer(body.len() as u64),
+        Data::Crlf,
+        Data::Bytes(Bytes::copy_from_slice(body.as_bytes())),
+    ])
+}
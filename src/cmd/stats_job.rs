@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use bytes::Bytes;
+use tokio::sync::Mutex;
+
+use crate::{codec::Data, queue::Queue};
+
+pub async fn stats_job(queue: Arc<Mutex<Queue>>, id: u64) -> Result<Vec<Data>> {
+    let queue = queue.lock().await;
+    let job = match queue.peek(id) {
+        Some(job) => job,
+        None => return Ok(vec![Data::String("NOT_FOUND".into())]),
+    };
+    let body = format!(
+        "---\n\
+         id: {}\n\
+         tube: {}\n\
+         state: {}\n\
+         pri: {}\n\
+         age: {}\n\
+         delay: {}\n\
+         ttr: {}\n\
+         time-left: {}\n\
+         file: 0\n\
+         reserves: {}\n\
+         timeouts: {}\n\
+         releases: {}\n\
+         buries: {}\n\
+         kicks: {}\n",
+        job.id,
+        job.tube,
+        job.state.as_str(),
+        job.pri,
+        job.created_at.elapsed().as_secs(),
+        job.delay,
+        job.ttr,
+        job.time_left(),
+        job.reserves,
+        job.timeouts,
+        job.releases,
+        job.buries,
+        job.kicks,
+    );
+    Ok(vec![
+        Data::String("OK".into()),
+        Data::Integer(body.len() as u64),
+        Data::Crlf,
+        Data::Bytes(Bytes::copy_from_slice(body.as_bytes())),
+    ])
+}
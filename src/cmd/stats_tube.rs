@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use bytes::Bytes;
+use tokio::sync::Mutex;
+
+use crate::{codec::Data, queue::Queue};
+
+pub async fn stats_tube(queue: Arc<Mutex<Queue>>, tube: String) -> Result<Vec<Data>> {
+    let queue = queue.lock().await;
+    let stats = match queue.tube_stats(&tube) {
+        Some(stats) => stats,
+        None => return Ok(vec![Data::String("NOT_FOUND".into())]),
+    };
+    let body = format!(
+        "---\n\
+         name: {}\n\
+         current-jobs-urgent: {}\n\
+         current-jobs-ready: {}\n\
+         current-jobs-reserved: {}\n\
+         current-jobs-delayed: {}\n\
+         current-jobs-buried: {}\n\
+         total-jobs: {}\n\
+         current-using: {}\n\
+         current-watching: {}\n\
+         current-waiting: {}\n\
+         cmd-delete: {}\n\
+         cmd-pause-tube: {}\n\
+         pause: {}\n\
+         pause-time-left: {}\n",
+        tube,
+        stats.urgent,
+        stats.ready,
+        stats.reserved,
+        stats.delayed,
+        stats.buried,
+        stats.total_jobs,
+        stats.using,
+        stats.watching,
+        stats.waiting,
+        stats.cmd_delete,
+        stats.cmd_pause_tube,
+        stats.pause,
+        stats.pause_time_left,
+    );
+    Ok(vec![
+        Data::String("OK".into()),
+        Data::Integer(body.len() as u64),
+        Data::Crlf,
+        Data::Bytes(Bytes::copy_from_slice(body.as_bytes())),
+    ])
+}
@@ -0,0 +1,16 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::Mutex;
+
+use crate::{codec::Data, connection::Connection, queue::Queue};
+
+pub async fn touch(
+    connection: &mut Connection,
+    queue: Arc<Mutex<Queue>>,
+    id: u64,
+) -> Result<Vec<Data>> {
+    let ttr = queue.lock().await.touch_job(id, connection.id())?.ttr;
+    connection.touch_reserved(id, ttr).await;
+    Ok(vec![Data::String("TOUCHED".into())])
+}
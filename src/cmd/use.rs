@@ -11,7 +11,8 @@ pub async fn use_tube(
     tube: String,
 ) -> Result<Vec<Data>> {
     let mut queue = queue.lock().await;
-    queue.new_tube(&tube);
+    queue.stop_using(connection.tube());
+    queue.start_using(&tube);
     connection.use_tube(&tube);
     Ok(vec![Data::String("USING".into()), Data::String(tube)])
 }
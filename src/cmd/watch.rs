@@ -1,11 +1,21 @@
+use std::sync::Arc;
+
 use anyhow::Result;
+use tokio::sync::Mutex;
 
-use crate::{codec::Data, connection::Connection};
+use crate::{codec::Data, connection::Connection, queue::Queue};
 
-pub fn watch(connection: &mut Connection, tube: String) -> Result<Vec<Data>> {
-    connection.watch(tube);
+pub async fn watch(
+    connection: &mut Connection,
+    queue: Arc<Mutex<Queue>>,
+    tube: String,
+) -> Result<Vec<Data>> {
+    let mut queue = queue.lock().await;
+    if connection.watch(tube.clone()) {
+        queue.watch_tube(&tube);
+    }
     Ok(vec![
         Data::String("WATCHING".into()),
-        Data::Integer(connection.get_watched_tubes().len() as u32),
+        Data::Integer(connection.get_watched_tubes().len() as u64),
     ])
 }
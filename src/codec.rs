@@ -1,5 +1,5 @@
 use anyhow::{anyhow, bail, Result};
-use bytes::{BufMut, Bytes, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
 use crate::settings;
@@ -7,7 +7,7 @@ use crate::settings;
 #[derive(Clone, Debug, PartialEq)]
 pub enum Data {
     String(String),
-    Integer(u32),
+    Integer(u64),
     Bytes(Bytes),
 
     /// Internal thing to format responses correctly
@@ -15,14 +15,28 @@ pub enum Data {
 }
 
 pub struct BeanstalkCodec {
-    next_index: usize,
+    state: State,
+    max_job_size: u32,
+}
+
+#[derive(Default)]
+enum State {
+    /// Waiting for a complete command line
+    #[default]
+    Line,
+    /// Got a `put` line and waiting for its body and the CRLF after it, which may take many reads
+    Body { frame: Vec<Data>, bytes: usize },
+    /// Throwing away the body of a job that was too big, so the next command can be read
+    Skip { bytes: usize },
+    /// Throwing away the rest of a line that was too long, up to and including its CRLF
+    Discard,
 }
 
 fn string_from_bytes(buf: &[u8]) -> Result<String> {
     String::from_utf8(buf.to_vec()).map_err(|_| anyhow!("INTERNAL_ERROR"))
 }
 
-fn num_from_bytes(buf: &[u8]) -> Result<u32> {
+fn num_from_bytes(buf: &[u8]) -> Result<u64> {
     // TODO: don't like this string allocation
     string_from_bytes(buf)?
         .parse()
@@ -33,95 +47,135 @@ fn valid_name_char(c: u8) -> bool {
     c.is_ascii_digit() || c.is_ascii_alphabetic() || b"-+/;.$_()".contains(&c)
 }
 
+/// Splits a command line (without its CRLF) into words.
+fn parse_line(line: &[u8]) -> Result<Vec<Data>> {
+    line.split(|&c| c == b' ')
+        .map(|word| match word.first() {
+            Some(c) if c.is_ascii_digit() => Ok(Data::Integer(num_from_bytes(word)?)),
+            Some(&c) if valid_name_char(c) && c != b'-' => {
+                if word.len() > 8 * 200 || word.iter().any(|&c| !valid_name_char(c)) {
+                    bail!("BAD_FORMAT");
+                }
+                Ok(Data::String(string_from_bytes(word)?))
+            }
+            _ => bail!("BAD_FORMAT"),
+        })
+        .collect()
+}
+
+/// Gets the body size from a `put` line, if that's what the line is.
+fn put_body_size(frame: &[Data]) -> Option<u64> {
+    match frame {
+        [Data::String(cmd), Data::Integer(_), Data::Integer(_), Data::Integer(_), Data::Integer(bytes)]
+            if cmd == "put" =>
+        {
+            Some(*bytes)
+        }
+        _ => None,
+    }
+}
+
+/// Drops everything in a buffer with no CRLF in it, except a trailing CR that might be the start
+/// of one.
+fn discard_partial_line(buf: &mut BytesMut) {
+    let keep = buf.ends_with(b"\r") as usize;
+    buf.advance(buf.len() - keep);
+}
+
+impl Default for BeanstalkCodec {
+    fn default() -> Self {
+        Self::new(settings::DEFAULT_MAX_JOB_SIZE)
+    }
+}
+
 impl BeanstalkCodec {
-    pub fn new() -> Self {
-        Self { next_index: 0 }
+    pub fn new(max_job_size: u32) -> Self {
+        Self {
+            state: State::default(),
+            max_job_size,
+        }
     }
 
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Vec<Data>>> {
-        let mut frame = Vec::new();
-        if buf.is_empty() {
-            return Ok(None);
-        }
-        self.next_index = 0;
-        if buf.len() > 8 * 224 {
-            bail!("BAD_FORMAT");
-        }
         loop {
-            let next_char = buf[self.next_index];
-            if let Some(end) = buf[self.next_index..]
-                .iter()
-                .position(u8::is_ascii_whitespace)
-            {
-                let data = if next_char.is_ascii_digit() {
-                    Data::Integer(num_from_bytes(
-                        &buf[self.next_index..self.next_index + end],
-                    )?)
-                } else if valid_name_char(next_char) && next_char != b'-' {
-                    if end > 8 * 200
-                        || buf[self.next_index..self.next_index + end]
-                            .iter()
-                            .any(|&c| !valid_name_char(c))
-                    {
+            match &mut self.state {
+                State::Line => {
+                    let Some(end) = buf.windows(2).position(|w| w == b"\r\n") else {
+                        if buf.len() >= settings::MAX_LINE_SIZE {
+                            // The rest of the line may not have arrived yet, and it mustn't be
+                            // read as a command of its own
+                            discard_partial_line(buf);
+                            self.state = State::Discard;
+                            bail!("BAD_FORMAT");
+                        }
+                        return Ok(None);
+                    };
+                    let line = buf.split_to(end + 2);
+                    if line.len() > settings::MAX_LINE_SIZE {
                         bail!("BAD_FORMAT");
                     }
-                    Data::String(string_from_bytes(
-                        &buf[self.next_index..self.next_index + end],
-                    )?)
-                } else {
-                    bail!("BAD_FORMAT");
-                };
-                match buf[self.next_index + end] {
-                    b' ' => {
-                        frame.push(data);
-                        self.next_index += end + 1;
-                    }
-                    b'\r' => {
-                        assert_eq!(buf[self.next_index + end + 1], b'\n');
-                        let maybe_num = if let Data::Integer(n) = data {
-                            if n > settings::MAX_JOB_SIZE {
-                                bail!("JOB_TOO_BIG");
-                            }
-                            Some(n)
-                        } else {
-                            None
+                    let frame = parse_line(&line[..end])?;
+                    // Any other shape of `put` is passed on as it is, so the command parser can answer
+                    // BAD_FORMAT without the next lines being read as a body
+                    let Some(bytes) = put_body_size(&frame) else {
+                        return Ok(Some(frame));
+                    };
+                    if bytes > self.max_job_size as u64 {
+                        self.state = State::Skip {
+                            bytes: bytes as usize + 2,
                         };
-                        frame.push(data);
-                        if let Some(num) = maybe_num {
-                            if buf.len() > self.next_index + end + 2 {
-                                if buf.len() < self.next_index + end + 4
-                                    || &buf[self.next_index + end + 2 + num as usize
-                                        ..self.next_index + end + 4 + num as usize]
-                                        != b"\r\n"
-                                {
-                                    bail!("EXPECTED_CRLF");
-                                }
-                                frame.push(Data::Bytes(Bytes::copy_from_slice(
-                                    &buf[self.next_index + end + 2
-                                        ..self.next_index + end + 2 + num as usize],
-                                )));
-                            }
-                        }
-                        break;
+                        bail!("JOB_TOO_BIG");
+                    }
+                    self.state = State::Body {
+                        frame,
+                        bytes: bytes as usize,
+                    };
+                }
+                State::Body { frame, bytes } => {
+                    if buf.len() < *bytes + 2 {
+                        buf.reserve(*bytes + 2 - buf.len());
+                        return Ok(None);
+                    }
+                    let mut frame = std::mem::take(frame);
+                    let body = buf.split_to(*bytes).freeze();
+                    let crlf = buf.split_to(2);
+                    self.state = State::Line;
+                    if &crlf[..] != b"\r\n" {
+                        bail!("EXPECTED_CRLF");
+                    }
+                    frame.push(Data::Bytes(body));
+                    return Ok(Some(frame));
+                }
+                State::Skip { bytes } => {
+                    let skipped = (*bytes).min(buf.len());
+                    buf.advance(skipped);
+                    *bytes -= skipped;
+                    if *bytes > 0 {
+                        return Ok(None);
                     }
-                    _ => bail!("BAD_FORMAT"),
+                    self.state = State::Line;
+                }
+                State::Discard => {
+                    let Some(end) = buf.windows(2).position(|w| w == b"\r\n") else {
+                        discard_partial_line(buf);
+                        return Ok(None);
+                    };
+                    buf.advance(end + 2);
+                    self.state = State::Line;
                 }
-            } else {
-                return Ok(None);
             }
         }
-        // TODO: this seems weird for like batch requests or something
-        buf.clear();
-        Ok(Some(frame))
     }
 }
 
 impl Decoder for BeanstalkCodec {
-    type Item = Vec<Data>;
+    /// Protocol errors are passed along as items rather than returned as errors, because
+    /// `FramedRead` stops reading after an error and the client should be able to carry on.
+    type Item = Result<Vec<Data>>;
     type Error = anyhow::Error;
 
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>> {
-        self.decode(buf)
+        Ok(self.decode(buf).transpose())
     }
 }
 
@@ -159,7 +213,7 @@ mod tests {
 
     #[test]
     fn decodes() {
-        let mut codec = BeanstalkCodec::new();
+        let mut codec = BeanstalkCodec::default();
         assert_eq!(
             codec
                 .decode(&mut BytesMut::from("put 1 11 101 1\r\nh\r\n"))
@@ -173,7 +227,7 @@ mod tests {
                 Data::Bytes(Bytes::from_static(b"h"))
             ])
         );
-        let mut codec = BeanstalkCodec::new();
+        let mut codec = BeanstalkCodec::default();
         assert_eq!(
             codec
                 .decode(&mut BytesMut::from("use default+$23\r\n"))
@@ -187,8 +241,8 @@ mod tests {
 
     #[test]
     fn int_too_big() {
-        let mut codec = BeanstalkCodec::new();
-        if let Err(e) = codec.decode(&mut BytesMut::from("4294967296\r\n")) {
+        let mut codec = BeanstalkCodec::default();
+        if let Err(e) = codec.decode(&mut BytesMut::from("18446744073709551616\r\n")) {
             assert_eq!(e.to_string(), "BAD_FORMAT");
         } else {
             panic!("did not error");
@@ -197,14 +251,14 @@ mod tests {
 
     #[test]
     fn invalid_name() {
-        let mut codec = BeanstalkCodec::new();
+        let mut codec = BeanstalkCodec::default();
         if let Err(e) = codec.decode(&mut BytesMut::from("-name\r\n")) {
             assert_eq!(e.to_string(), "BAD_FORMAT");
         } else {
             panic!("did not error");
         }
 
-        let mut codec = BeanstalkCodec::new();
+        let mut codec = BeanstalkCodec::default();
         if let Err(e) = codec.decode(&mut BytesMut::from("name^\r\n")) {
             assert_eq!(e.to_string(), "BAD_FORMAT");
         } else {
@@ -214,7 +268,7 @@ mod tests {
 
     #[test]
     fn too_long() {
-        let mut codec = BeanstalkCodec::new();
+        let mut codec = BeanstalkCodec::default();
         if let Err(e) = codec.decode(&mut BytesMut::from(
             &format!("{}\r\n", "a".repeat(8 * 224))[..],
         )) {
@@ -223,7 +277,7 @@ mod tests {
             panic!("did not error");
         }
 
-        let mut codec = BeanstalkCodec::new();
+        let mut codec = BeanstalkCodec::default();
         if let Err(e) = codec.decode(&mut BytesMut::from(
             &format!("put {}\r\n", "a".repeat(8 * 201))[..],
         )) {
@@ -232,9 +286,9 @@ mod tests {
             panic!("did not error");
         }
 
-        let mut codec = BeanstalkCodec::new();
+        let mut codec = BeanstalkCodec::default();
         if let Err(e) = codec.decode(&mut BytesMut::from(
-            &format!("put 1 1 1 {}\r\n", settings::MAX_JOB_SIZE + 1)[..],
+            &format!("put 1 1 1 {}\r\n", settings::DEFAULT_MAX_JOB_SIZE + 1)[..],
         )) {
             assert_eq!(e.to_string(), "JOB_TOO_BIG");
         } else {
@@ -242,13 +296,148 @@ mod tests {
         }
     }
 
+    #[test]
+    fn too_long_across_reads() {
+        let mut codec = BeanstalkCodec::default();
+        let mut buf = BytesMut::from(&format!("use {}", "a".repeat(300))[..]);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().to_string(),
+            "BAD_FORMAT"
+        );
+        buf.extend_from_slice(b"aaaaaaaaa\r");
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"\ndelete 1\r\n");
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(vec![Data::String("delete".into()), Data::Integer(1)])
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn put_wrong_arg_count() {
+        let mut codec = BeanstalkCodec::default();
+        let mut buf =
+            BytesMut::from("put 0 0 60\r\nuse foo\r\nput 0 0 60 5 9\r\nlist-tube-used\r\n");
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(vec![
+                Data::String("put".into()),
+                Data::Integer(0),
+                Data::Integer(0),
+                Data::Integer(60)
+            ])
+        );
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(vec![Data::String("use".into()), Data::String("foo".into())])
+        );
+        assert_eq!(
+            codec.decode(&mut buf).unwrap().unwrap().last(),
+            Some(&Data::Integer(9))
+        );
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(vec![Data::String("list-tube-used".into())])
+        );
+        assert!(buf.is_empty());
+    }
+
     #[test]
     fn no_crlf() {
-        let mut codec = BeanstalkCodec::new();
-        if let Err(e) = codec.decode(&mut BytesMut::from("put 1 1 1 1\r\nh")) {
+        let mut codec = BeanstalkCodec::default();
+        if let Err(e) = codec.decode(&mut BytesMut::from("put 1 1 1 1\r\nhxx")) {
             assert_eq!(e.to_string(), "EXPECTED_CRLF");
         } else {
             panic!("did not error");
         }
     }
+
+    #[test]
+    fn pipelined() {
+        let mut codec = BeanstalkCodec::default();
+        let mut buf =
+            BytesMut::from("put 0 0 1 1\r\na\r\nput 0 0 1 2\r\nbc\r\ndelete 1\r\nuse tube\r\n");
+        let put = |data: &'static [u8]| {
+            Some(vec![
+                Data::String("put".into()),
+                Data::Integer(0),
+                Data::Integer(0),
+                Data::Integer(1),
+                Data::Integer(data.len() as u64),
+                Data::Bytes(Bytes::from_static(data)),
+            ])
+        };
+        assert_eq!(codec.decode(&mut buf).unwrap(), put(b"a"));
+        assert_eq!(codec.decode(&mut buf).unwrap(), put(b"bc"));
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(vec![Data::String("delete".into()), Data::Integer(1)])
+        );
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(vec![
+                Data::String("use".into()),
+                Data::String("tube".into())
+            ])
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn incomplete_frame() {
+        let mut codec = BeanstalkCodec::default();
+        let mut buf = BytesMut::from("delete 1\r\nput 0 0 1 5\r\nhel");
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(vec![Data::String("delete".into()), Data::Integer(1)])
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(codec.decode(&mut BytesMut::from("put 0 ")).unwrap(), None);
+        buf.extend_from_slice(b"lo\r\n");
+        assert_eq!(
+            codec.decode(&mut buf).unwrap().unwrap().last(),
+            Some(&Data::Bytes(Bytes::from_static(b"hello")))
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn streamed_body() {
+        let mut codec = BeanstalkCodec::default();
+        let body = "x".repeat(settings::DEFAULT_MAX_JOB_SIZE as usize);
+        let input = format!("put 0 0 1 {}\r\n{body}\r\nuse tube\r\n", body.len());
+        let mut buf = BytesMut::new();
+        let mut frames = Vec::new();
+        for chunk in input.as_bytes().chunks(1000) {
+            buf.extend_from_slice(chunk);
+            while let Some(frame) = codec.decode(&mut buf).unwrap() {
+                frames.push(frame);
+            }
+        }
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            frames[0].last(),
+            Some(&Data::Bytes(Bytes::from(body.into_bytes())))
+        );
+        assert_eq!(frames[1][0], Data::String("use".into()));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn skips_big_job() {
+        let mut codec = BeanstalkCodec::new(5);
+        let mut buf = BytesMut::from("put 0 0 1 10\r\n01234");
+        assert_eq!(
+            codec.decode(&mut buf).unwrap_err().to_string(),
+            "JOB_TOO_BIG"
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"56789\r\nput 0 0 1 5\r\nhello\r\n");
+        assert_eq!(
+            codec.decode(&mut buf).unwrap().unwrap().last(),
+            Some(&Data::Bytes(Bytes::from_static(b"hello")))
+        );
+    }
 }
@@ -0,0 +1,246 @@
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::settings;
+
+pub const USAGE: &str = "\
+Usage: beanstalkrs [OPTIONS]
+
+Options:
+ -b DIR     write-ahead log directory
+ -f MS      fsync at most once every MS milliseconds (use -f0 for \"always fsync\")
+ -F         never fsync (default)
+ -l ADDR    listen on address (default is 0.0.0.0)
+ -p PORT    listen on port (default is 11300)
+ -z BYTES   set the maximum job size in bytes (default is 65535)
+ -s BYTES   set the size of each write-ahead log file (default is 10485760)
+ -c         compact the binlog (default)
+ -n         do not compact the binlog
+ -v         show version information
+ -V         increase verbosity
+ -h         show this help
+ --config FILE
+            read `key: value` settings from FILE, which options on the command line override
+
+The binlog isn't supported yet, so -b, -f, -F, -s, -c and -n are accepted but have no effect.
+";
+
+/// Server options. Settings from a config file can be overridden on the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub listen_addr: String,
+    pub port: u16,
+    /// Largest job body accepted by `put`, in bytes
+    pub max_job_size: u32,
+    pub binlog_dir: Option<PathBuf>,
+    /// How often to fsync the binlog in milliseconds, or `None` to leave it to the OS
+    pub fsync_ms: Option<u32>,
+    pub binlog_size: u32,
+    pub compact_binlog: bool,
+    pub verbosity: u32,
+}
+
+/// What the command line asked for
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Serve(Config),
+    Version,
+    Help,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen_addr: "0.0.0.0".into(),
+            port: settings::DEFAULT_PORT,
+            max_job_size: settings::DEFAULT_MAX_JOB_SIZE,
+            binlog_dir: None,
+            fsync_ms: None,
+            binlog_size: settings::DEFAULT_BINLOG_SIZE,
+            compact_binlog: true,
+            verbosity: 0,
+        }
+    }
+}
+
+impl Command {
+    /// Parses command line arguments, not including the program name. Options are the same as
+    /// beanstalkd's, so values can be attached (`-p11300`) and flags combined (`-VV`).
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        let mut overrides = Vec::new();
+        let mut verbosity = 0;
+        while let Some(arg) = args.next() {
+            if arg == "--config" {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--config requires a value"))?;
+                let contents = std::fs::read_to_string(&path)
+                    .with_context(|| format!("couldn't read {path}"))?;
+                config.apply_file(&contents)?;
+                continue;
+            }
+            let flags = match arg.strip_prefix('-') {
+                Some(flags) if !flags.is_empty() && !flags.starts_with('-') => flags,
+                _ => bail!("unknown argument {arg}"),
+            };
+            for (i, flag) in flags.char_indices() {
+                let key = match flag {
+                    'b' => "binlog-dir",
+                    'f' => "fsync",
+                    'l' => "listen-addr",
+                    'p' => "port",
+                    's' => "binlog-size",
+                    'z' => "max-job-size",
+                    'F' => {
+                        overrides.push(("fsync", "never".to_string()));
+                        continue;
+                    }
+                    'c' | 'n' => {
+                        overrides.push(("compact", (flag == 'c').to_string()));
+                        continue;
+                    }
+                    'V' => {
+                        verbosity += 1;
+                        continue;
+                    }
+                    'v' => return Ok(Self::Version),
+                    'h' => return Ok(Self::Help),
+                    _ => bail!("unknown option -{flag}"),
+                };
+                // The value is either the rest of this argument or the next one
+                let rest = &flags[i + flag.len_utf8()..];
+                let value = if rest.is_empty() {
+                    args.next()
+                        .ok_or_else(|| anyhow!("-{flag} requires a value"))?
+                } else {
+                    rest.to_string()
+                };
+                overrides.push((key, value));
+                break;
+            }
+        }
+        for (key, value) in overrides {
+            config.set(key, &value)?;
+        }
+        config.verbosity += verbosity;
+        Ok(Self::Serve(config))
+    }
+}
+
+impl Config {
+    /// Reads `key: value` lines. Blank lines and lines starting with `#` are ignored.
+    pub fn apply_file(&mut self, contents: &str) -> Result<()> {
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once(':')
+                .ok_or_else(|| anyhow!("expected `key: value`, got {line:?}"))?;
+            self.set(key.trim(), value.trim())?;
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "listen-addr" => self.listen_addr = value.to_string(),
+            "port" => self.port = parse(key, value)?,
+            "max-job-size" => self.max_job_size = parse(key, value)?,
+            "binlog-dir" => self.binlog_dir = Some(value.into()),
+            "fsync" if value == "never" => self.fsync_ms = None,
+            "fsync" => self.fsync_ms = Some(parse(key, value)?),
+            "binlog-size" => self.binlog_size = parse(key, value)?,
+            "compact" => self.compact_binlog = parse(key, value)?,
+            "verbosity" => self.verbosity = parse(key, value)?,
+            _ => bail!("unknown setting {key}"),
+        }
+        Ok(())
+    }
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("invalid {key} {value:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> Result<Config> {
+        match Command::from_args(args.split_whitespace().map(String::from))? {
+            Command::Serve(config) => Ok(config),
+            command => panic!("expected config, got {command:?}"),
+        }
+    }
+
+    #[test]
+    fn max_job_size() {
+        assert_eq!(
+            args("").unwrap().max_job_size,
+            settings::DEFAULT_MAX_JOB_SIZE
+        );
+        assert_eq!(args("-z 100").unwrap().max_job_size, 100);
+        assert!(args("-z").is_err());
+        assert!(args("-z big").is_err());
+        assert!(args("-x").is_err());
+
+        let mut config = Config::default();
+        config
+            .apply_file("# limits\n\nmax-job-size: 2048\n")
+            .unwrap();
+        assert_eq!(config.max_job_size, 2048);
+        assert!(config.apply_file("max-job-size 10").is_err());
+        assert!(config.apply_file("unknown: 10").is_err());
+    }
+
+    #[test]
+    fn args_override_file() {
+        let path = std::env::temp_dir().join(format!("beanstalkrs-{}.conf", std::process::id()));
+        std::fs::write(&path, "max-job-size: 10\nport: 1234\n").unwrap();
+        let path = path.to_str().unwrap();
+        let config = args(&format!("--config {path}")).unwrap();
+        assert_eq!((config.max_job_size, config.port), (10, 1234));
+        assert_eq!(
+            args(&format!("-z 20 --config {path}"))
+                .unwrap()
+                .max_job_size,
+            20
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn beanstalkd_flags() {
+        let config =
+            args("-l 127.0.0.1 -p11301 -b /var/lib/beanstalkd -f0 -s 1000 -n -VV").unwrap();
+        assert_eq!(
+            config,
+            Config {
+                listen_addr: "127.0.0.1".into(),
+                port: 11301,
+                max_job_size: settings::DEFAULT_MAX_JOB_SIZE,
+                binlog_dir: Some("/var/lib/beanstalkd".into()),
+                fsync_ms: Some(0),
+                binlog_size: 1000,
+                compact_binlog: false,
+                verbosity: 2,
+            }
+        );
+        let config = args("-f 50 -F -c").unwrap();
+        assert_eq!(config.fsync_ms, None);
+        assert!(config.compact_binlog);
+        assert_eq!(args("").unwrap().port, 11300);
+        assert!(args("-p 70000").is_err());
+        assert!(args("port").is_err());
+
+        let command = |args: &str| Command::from_args(args.split_whitespace().map(String::from));
+        assert_eq!(command("-v").unwrap(), Command::Version);
+        assert_eq!(command("-V -h").unwrap(), Command::Help);
+    }
+}
@@ -1,65 +1,134 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use anyhow::Result;
-use futures_util::{stream::FuturesUnordered, SinkExt, StreamExt};
+use futures_util::{SinkExt, StreamExt};
 use tokio::{
-    net::TcpStream,
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
     select,
     sync::{mpsc, Mutex, Notify},
-    time::{sleep, Duration},
+    time::{Duration, Instant},
+};
+use tokio_util::{
+    codec::{FramedRead, FramedWrite},
+    time::DelayQueue,
 };
-use tokio_util::codec::Framed;
 
 use crate::{
     cmd::Cmd,
     codec::{BeanstalkCodec, Data},
     queue::Queue,
+    settings,
 };
 
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
 pub struct Connection {
+    id: u64,
     tube: String,
     watch: Vec<String>,
-    stream: Framed<TcpStream, BeanstalkCodec>,
+    stream: FramedRead<OwnedReadHalf, BeanstalkCodec>,
+    /// Frames read while a command was blocked, to be handled once it's done
+    pending: VecDeque<Result<Vec<Data>>>,
+    /// Set when the client hangs up while a command is blocked
+    hung_up: bool,
+    /// Replies are written to the socket by their own task, so nothing needs to hold the
+    /// connection to talk to the client
+    frame_tx: mpsc::Sender<Vec<Data>>,
 
     reserved_job_tx: mpsc::Sender<ReserveCommand>,
+    /// When each job reserved by this connection times out. Entries for jobs that have already
+    /// timed out are ignored.
+    deadlines: HashMap<u64, Instant>,
+
+    producer: bool,
+    worker: bool,
 
     shutdown: Notify,
 }
 
 impl Connection {
-    pub fn new(stream: Framed<TcpStream, BeanstalkCodec>) -> Arc<Mutex<Self>> {
+    pub fn new(socket: TcpStream, queue: Arc<Mutex<Queue>>, max_job_size: u32) -> Self {
         let (reserved_job_tx, reserved_job_rx) = mpsc::channel(100);
+        let (frame_tx, frame_rx) = mpsc::channel(100);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
 
-        let connection = Arc::new(Mutex::new(Self {
+        let (reader, writer) = socket.into_split();
+        tokio::spawn(write_frames(
+            frame_rx,
+            FramedWrite::new(writer, BeanstalkCodec::new(max_job_size)),
+        ));
+        tokio::spawn(watch_reserved_jobs(reserved_job_rx, id, queue));
+        Self {
+            id,
             tube: "default".into(),
             watch: vec!["default".into()],
-            stream,
+            stream: FramedRead::new(reader, BeanstalkCodec::new(max_job_size)),
+            pending: VecDeque::new(),
+            hung_up: false,
+            frame_tx,
             reserved_job_tx,
+            deadlines: HashMap::new(),
+            producer: false,
+            worker: false,
             shutdown: Notify::new(),
-        }));
-        tokio::spawn(watch_reserved_jobs(reserved_job_rx, connection.clone()));
-        connection
+        }
     }
 
     pub async fn run(&mut self, queue: Arc<Mutex<Queue>>) {
-        loop {
-            select! {
-                Some(input) = self.stream.next() => {
-                    match self.handle_frame(queue.clone(), input).await {
-                        Ok(data) => self.send_frame(data).await,
-                        Err(e) => self.send_frame(vec![Data::String(e.to_string())]).await,
-                    }
-                }
-                _ = self.shutdown.notified() => {
-                    break;
-                }
+        {
+            let mut queue = queue.lock().await;
+            queue.stats.connection_opened();
+            queue.start_using(&self.tube);
+            for tube in &self.watch {
+                queue.watch_tube(tube);
+            }
+        }
+        while !self.hung_up {
+            let input = match self.pending.pop_front() {
+                Some(input) => input,
+                None => select! {
+                    input = self.stream.next() => match input {
+                        Some(Ok(input)) => input,
+                        // The client disconnected
+                        Some(Err(_)) | None => break,
+                    },
+                    _ = self.shutdown.notified() => break,
+                },
+            };
+            match self.handle_frame(queue.clone(), input).await {
+                Ok(data) => self.send_frame(data).await,
+                Err(e) => self.send_frame(vec![Data::String(e.to_string())]).await,
             }
         }
+        let _ = self.reserved_job_tx.send(ReserveCommand::Close).await;
+        let mut queue = queue.lock().await;
+        queue.release_all(self.id);
+        queue.stats.current_connections -= 1;
+        if self.producer {
+            queue.stats.current_producers -= 1;
+        }
+        if self.worker {
+            queue.stats.current_workers -= 1;
+        }
+        queue.stop_using(&self.tube);
+        for tube in &self.watch {
+            queue.ignore_tube(tube);
+        }
     }
 
     async fn send_frame(&mut self, frame: Vec<Data>) {
+        // If the client has gone away the next read in `run` will notice
         if !frame.is_empty() {
-            self.stream.send(frame).await.unwrap();
+            let _ = self.frame_tx.send(frame).await;
         }
     }
 
@@ -72,6 +141,20 @@ impl Connection {
         cmd.run(self, queue).await
     }
 
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Marks this connection as having put a job. Returns `false` if it already had.
+    pub fn make_producer(&mut self) -> bool {
+        !std::mem::replace(&mut self.producer, true)
+    }
+
+    /// Marks this connection as having reserved a job. Returns `false` if it already had.
+    pub fn make_worker(&mut self) -> bool {
+        !std::mem::replace(&mut self.worker, true)
+    }
+
     pub fn use_tube(&mut self, tube: impl ToString) {
         self.tube = tube.to_string();
     }
@@ -84,13 +167,18 @@ impl Connection {
         &self.watch
     }
 
-    pub fn watch(&mut self, tube: String) {
+    /// Returns `false` if the tube was already being watched.
+    pub fn watch(&mut self, tube: String) -> bool {
         if !self.watch.contains(&tube) {
             self.watch.push(tube);
+            true
+        } else {
+            false
         }
     }
 
-    pub fn ignore(&mut self, tube: String) {
+    /// Returns `false` if the tube wasn't being watched.
+    pub fn ignore(&mut self, tube: String) -> bool {
         if let Some((i, _)) = self
             .watch
             .iter()
@@ -98,23 +186,71 @@ impl Connection {
             .find(|(_, name)| name == &&tube)
         {
             self.watch.remove(i);
+            true
+        } else {
+            false
         }
     }
 
-    pub async fn add_reserved(&mut self, id: u32, ttr: u32) {
+    pub async fn add_reserved(&mut self, id: u64, ttr: u32) {
+        let now = Instant::now();
+        self.deadlines.retain(|_, deadline| *deadline > now);
+        self.deadlines
+            .insert(id, now + Duration::from_secs(ttr as u64));
         self.reserved_job_tx
             .send(ReserveCommand::Reserve { id, ttr })
             .await
             .unwrap();
     }
 
-    pub async fn remove_reserved(&mut self, id: u32) {
+    pub async fn touch_reserved(&mut self, id: u64, ttr: u32) {
+        self.deadlines
+            .insert(id, Instant::now() + Duration::from_secs(ttr as u64));
+        self.reserved_job_tx
+            .send(ReserveCommand::Touch { id, ttr })
+            .await
+            .unwrap();
+    }
+
+    pub async fn remove_reserved(&mut self, id: u64) {
+        self.deadlines.remove(&id);
         self.reserved_job_tx
             .send(ReserveCommand::Remove { id })
             .await
             .unwrap();
     }
 
+    /// When the safety margin starts for the reserved job closest to timing out, if there is one.
+    pub fn safety_margin_at(&self) -> Option<Instant> {
+        let now = Instant::now();
+        self.deadlines
+            .values()
+            .filter(|deadline| **deadline > now)
+            .min()
+            .map(|deadline| *deadline - Duration::from_secs(settings::SAFETY_MARGIN))
+    }
+
+    /// Whether a job reserved by this connection is about to time out.
+    pub fn deadline_soon(&self) -> bool {
+        self.safety_margin_at()
+            .is_some_and(|margin_at| margin_at <= Instant::now())
+    }
+
+    /// Resolves once the client has closed its end of the socket. This is for noticing a
+    /// disconnect while a command is blocked, since `run` isn't reading then. Anything the client
+    /// sends in the meantime is kept for `run` to handle afterwards.
+    pub async fn closed(&mut self) {
+        loop {
+            match self.stream.next().await {
+                Some(Ok(input)) => self.pending.push_back(input),
+                Some(Err(_)) | None => {
+                    self.hung_up = true;
+                    return;
+                }
+            }
+        }
+    }
+
     pub fn quit(&mut self) {
         self.shutdown.notify_one();
     }
@@ -122,44 +258,56 @@ impl Connection {
 
 #[derive(Debug)]
 pub enum ReserveCommand {
-    Reserve { id: u32, ttr: u32 },
-    Remove { id: u32 },
+    Reserve { id: u64, ttr: u32 },
+    Touch { id: u64, ttr: u32 },
+    Remove { id: u64 },
+    Close,
+}
+
+/// Writes frames to the socket in the order they were sent, until every sender is dropped.
+async fn write_frames(
+    mut frame_rx: mpsc::Receiver<Vec<Data>>,
+    mut sink: FramedWrite<OwnedWriteHalf, BeanstalkCodec>,
+) {
+    while let Some(frame) = frame_rx.recv().await {
+        if sink.send(frame).await.is_err() {
+            break;
+        }
+    }
 }
 
 async fn watch_reserved_jobs(
     mut reserved_job_rx: mpsc::Receiver<ReserveCommand>,
-    connection: Arc<Mutex<Connection>>,
+    connection_id: u64,
+    queue: Arc<Mutex<Queue>>,
 ) {
-    let mut jobs = FuturesUnordered::new();
-    // TODO: is this bad
-    let mut removed = Vec::new();
+    let mut timers = DelayQueue::new();
+    let mut keys = HashMap::new();
     loop {
         select! {
             Some(cmd) = reserved_job_rx.recv() => {
                 match cmd {
-                    ReserveCommand::Reserve { id, ttr } => {
-                        jobs.push(tokio::spawn(async move {
-                            sleep(Duration::from_secs(ttr as u64 - 1)).await;
-                            (id, true)
-                        }));
+                    ReserveCommand::Reserve { id, ttr } | ReserveCommand::Touch { id, ttr } => {
+                        let ttr = Duration::from_secs(ttr as u64);
+                        match keys.get(&id) {
+                            Some(key) => timers.reset(key, ttr),
+                            None => {
+                                keys.insert(id, timers.insert(id, ttr));
+                            }
+                        }
                     },
-                    ReserveCommand::Remove { id } => removed.push(id)
+                    ReserveCommand::Remove { id } => {
+                        if let Some(key) = keys.remove(&id) {
+                            timers.remove(&key);
+                        }
+                    }
+                    ReserveCommand::Close => break,
                 }
             }
-            Some(Ok((job, safety_margin))) = jobs.next() => {
-                if let Some(i) = removed.iter().find(|id| id == &&job) {
-                    removed.remove(*i as usize);
-                } else if safety_margin {
-                    jobs.push(tokio::spawn(async move {
-                            sleep(Duration::from_secs(1)).await;
-                            (job, false)
-                    }));
-                    let mut connection = connection.lock().await;
-                    connection.send_frame(vec![Data::String("DEADLINE_SOON".into())]).await;
-                } else {
-                    // TODO: release
-                    removed.push(job);
-                }
+            Some(expired) = timers.next() => {
+                let job = expired.into_inner();
+                keys.remove(&job);
+                queue.lock().await.timeout_job(job, connection_id);
             }
         }
     }
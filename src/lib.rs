@@ -0,0 +1,9 @@
+mod cmd;
+pub mod codec;
+pub mod config;
+pub mod connection;
+mod parser;
+pub mod queue;
+pub mod server;
+mod settings;
+mod stats;
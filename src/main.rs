@@ -1,48 +1,55 @@
-use std::sync::Arc;
-
-use codec::BeanstalkCodec;
-use connection::Connection;
-
-use queue::Queue;
-use tokio::{
-    net::TcpListener,
-    sync::{mpsc, Mutex},
+use beanstalkrs::{
+    config::{Command, Config, USAGE},
+    server,
 };
-use tokio_util::codec::Decoder;
-
-mod cmd;
-mod codec;
-mod connection;
-mod parser;
-mod queue;
-mod settings;
+use tokio::net::TcpListener;
 
 #[tokio::main]
 async fn main() {
-    let listener = TcpListener::bind("127.0.0.1:3000").await.unwrap();
-    let (ready_job_tx, ready_job_rx) = mpsc::channel(100);
-    let queue = Arc::new(Mutex::new(Queue::new(ready_job_tx)));
-
-    watch_delay_jobs(queue.clone(), ready_job_rx);
-
-    loop {
-        let (socket, _) = listener.accept().await.unwrap();
-        let queue = queue.clone();
-
-        tokio::spawn(async move {
-            let codec = BeanstalkCodec::new();
-            let connection = Connection::new(codec.framed(socket));
-            let mut connection = connection.lock().await;
-            connection.run(queue).await;
-        });
+    let config = match Command::from_args(std::env::args().skip(1)) {
+        Ok(Command::Serve(config)) => config,
+        Ok(Command::Version) => {
+            println!("beanstalkrs {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Ok(Command::Help) => {
+            print!("{USAGE}");
+            return;
+        }
+        Err(e) => {
+            eprintln!("beanstalkrs: {e:#}\n\n{USAGE}");
+            std::process::exit(5);
+        }
+    };
+    let default = Config::default();
+    let binlog_options = config.fsync_ms != default.fsync_ms
+        || config.binlog_size != default.binlog_size
+        || config.compact_binlog != default.compact_binlog;
+    if let Some(dir) = &config.binlog_dir {
+        eprintln!(
+            "beanstalkrs: warning: the binlog isn't supported yet, so nothing will be written to {}",
+            dir.display()
+        );
+    } else if binlog_options {
+        eprintln!(
+            "beanstalkrs: warning: the binlog isn't supported yet, so -f, -s and -n have no effect"
+        );
     }
-}
-
-fn watch_delay_jobs(queue: Arc<Mutex<Queue>>, mut ready_job_rx: mpsc::Receiver<(String, u32)>) {
-    tokio::spawn(async move {
-        if let Some((tube, id)) = ready_job_rx.recv().await {
-            let mut queue = queue.lock().await;
-            queue.queue_job(tube, id);
+    let listener = match TcpListener::bind((config.listen_addr.as_str(), config.port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!(
+                "beanstalkrs: couldn't listen on {}:{}: {e}",
+                config.listen_addr, config.port
+            );
+            std::process::exit(111);
         }
-    });
+    };
+    if config.verbosity > 0 {
+        eprintln!(
+            "beanstalkrs: listening on {}",
+            listener.local_addr().unwrap()
+        );
+    }
+    server::run(listener, config).await;
 }
@@ -1,4 +1,4 @@
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use bytes::Bytes;
 
 use crate::codec::Data;
@@ -22,9 +22,9 @@ impl Parser {
         }
     }
 
-    pub fn consume_integer(&mut self) -> Result<u32> {
+    pub fn consume_integer<T: TryFrom<u64>>(&mut self) -> Result<T> {
         if let Some(Data::Integer(i)) = self.data.next() {
-            Ok(i)
+            i.try_into().map_err(|_| anyhow!("BAD_FORMAT"))
         } else {
             bail!("BAD_FORMAT");
         }
@@ -1,61 +1,206 @@
-use futures_util::StreamExt;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 
+use anyhow::{anyhow, bail, Result};
 use bytes::Bytes;
-use futures_util::stream::FuturesUnordered;
 use tokio::{
     select,
-    sync::mpsc,
-    time::{sleep, Duration},
+    sync::{mpsc, oneshot, watch},
+    time::{sleep_until, Duration, Instant},
 };
 
+use crate::{settings, stats::Stats};
+
 pub struct Queue {
     // TODO: `Connection` struct with watch list
     tubes: HashMap<String, Tube>,
-    jobs: Vec<Job>,
-    new_job_tx: mpsc::Sender<(String, u32, u32)>,
+    jobs: HashMap<u64, Job>,
+    /// Ids of the jobs each connection has reserved, so they can be released when it closes
+    reserved: HashMap<u64, HashSet<u64>>,
+    /// Ids are never reused, even after a job is deleted. Once there is a binlog this should be
+    /// restored from it on startup.
+    last_id: u64,
+    /// When the next delayed job in any tube becomes ready. The delay timer sleeps until then.
+    next_delay_tx: watch::Sender<Option<Instant>>,
+    /// Connections blocked in a reserve, by connection id. Each tube they watch also queues
+    /// their id so they are served in the order they arrived.
+    waiters: HashMap<u64, Waiter>,
+    pub stats: Stats,
 }
 
 #[derive(Default)]
 pub struct Tube {
-    ready: VecDeque<u32>,
-    delay: Vec<u32>,
-    smallest_pri: u32,
+    /// Ready jobs ordered by `(pri, id)`. This is used as a priority queue, but unlike a binary
+    /// heap it can also remove arbitrary jobs in O(log n) for `delete` and `reserve-job`.
+    ready: BTreeSet<(u32, u64)>,
+    /// Delayed jobs ordered by when they become ready, used as a min-heap in the same way
+    delay: BTreeSet<(Instant, u64)>,
+
+    /// Buried jobs in the order they were buried, keyed by `Job::bury_seq` so they can be removed
+    /// in O(log n) as well. In the original implementation this is a FIFO linked list.
+    buried: BTreeSet<(u64, u64)>,
+    last_bury_seq: u64,
+    /// Jobs from this tube currently reserved by any connection
+    reserved: usize,
+
+    total_jobs: u64,
+    using: u32,
+    watching: u32,
+    /// Connections waiting on a reserve from this tube, oldest first
+    waiters: VecDeque<u64>,
+    cmd_delete: u64,
+    cmd_pause_tube: u64,
+    pause: u32,
+    pause_until: Option<Instant>,
+}
 
-    /// In original implementation this is a FIFO linked list
-    buried: Vec<u32>,
+struct Waiter {
+    tubes: Vec<String>,
+    tx: oneshot::Sender<Job>,
+}
+
+pub struct TubeStats {
+    pub urgent: usize,
+    pub ready: usize,
+    pub reserved: usize,
+    pub delayed: usize,
+    pub buried: usize,
+    pub total_jobs: u64,
+    pub using: u32,
+    pub watching: u32,
+    pub waiting: u32,
+    pub cmd_delete: u64,
+    pub cmd_pause_tube: u64,
+    pub pause: u32,
+    pub pause_time_left: u64,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Job {
-    pub id: u32,
+    pub id: u64,
+    pub tube: String,
     pub ttr: u32,
     pub pri: u32,
     pub data: Bytes,
+
+    pub state: JobState,
+    pub created_at: Instant,
+    pub delay: u32,
+
+    /// When a delayed job will be moved to the ready queue or a reservation will expire
+    pub deadline_at: Option<Instant>,
+
+    pub reserves: u32,
+    pub timeouts: u32,
+    pub releases: u32,
+    pub buries: u32,
+    pub kicks: u32,
+
+    /// Position in the tube's buried list, while the job is buried
+    bury_seq: u64,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum JobState {
+    Ready,
+    Delayed,
+    /// Reserved by the connection with this id
+    Reserved(u64),
+    Buried,
 }
 
 impl Queue {
-    pub fn new(ready_job_tx: mpsc::Sender<(String, u32)>) -> Self {
-        let (new_job_tx, new_job_rx) = mpsc::channel(100);
-        // This is an implementation detail that differs from the original Beanstalk. Instead of each
-        // tube having a delay queue, they are all in this one to make async polling easier.
-        tokio::spawn(watch_delayed_jobs(new_job_rx, ready_job_tx));
+    /// `ready_job_tx` is notified whenever a delayed job is due, after which `promote_delayed`
+    /// should be called.
+    pub fn new(ready_job_tx: mpsc::Sender<()>) -> Self {
+        let (next_delay_tx, next_delay_rx) = watch::channel(None);
+        tokio::spawn(watch_delayed_jobs(next_delay_rx, ready_job_tx));
         Self {
             tubes: HashMap::from([("default".to_string(), Tube::default())]),
-            jobs: Vec::new(),
-            new_job_tx,
+            jobs: HashMap::new(),
+            reserved: HashMap::new(),
+            last_id: 0,
+            next_delay_tx,
+            waiters: HashMap::new(),
+            stats: Stats::new(),
+        }
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn job_mut(&mut self, id: &u64) -> &mut Job {
+        self.jobs.get_mut(id).unwrap()
+    }
+
+    /// Returns the job with the given id if it is reserved by `connection_id`, and `NOT_FOUND`
+    /// otherwise.
+    fn reserved_job_mut(&mut self, id: u64, connection_id: u64) -> Result<&mut Job> {
+        self.jobs
+            .get_mut(&id)
+            .filter(|job| job.state == JobState::Reserved(connection_id))
+            .ok_or_else(|| anyhow!("NOT_FOUND"))
+    }
+
+    /// Moves a job into a new state, taking it out of the tube list for its old state and adding
+    /// it to the list for the new one. Fails with `NOT_FOUND` if the job doesn't exist or can't
+    /// make that transition.
+    fn transition(&mut self, id: u64, to: JobState) -> Result<&mut Job> {
+        let job = self.jobs.get_mut(&id).ok_or_else(|| anyhow!("NOT_FOUND"))?;
+        if !job.state.can_become(to) {
+            bail!("NOT_FOUND");
+        }
+        let name = job.tube.clone();
+        let tube = self.tubes.entry(name.clone()).or_default();
+        tube.remove(job);
+        let from = job.transition(to)?;
+        tube.insert(job);
+
+        if let JobState::Reserved(connection_id) = from {
+            self.unmark_reserved(connection_id, id);
+        }
+        if let JobState::Reserved(connection_id) = to {
+            self.reserved.entry(connection_id).or_default().insert(id);
+        }
+        if from == JobState::Delayed || to == JobState::Delayed {
+            self.reset_delay_timer();
+        }
+        if to == JobState::Ready {
+            self.process_waiters(&name);
+        }
+        Ok(self.job_mut(&id))
+    }
+
+    fn unmark_reserved(&mut self, connection_id: u64, id: u64) {
+        if let Some(reserved) = self.reserved.get_mut(&connection_id) {
+            reserved.remove(&id);
+            if reserved.is_empty() {
+                self.reserved.remove(&connection_id);
+            }
         }
     }
 
-    fn job(&self, id: &u32) -> &Job {
-        self.jobs.iter().find(|job| &job.id == id).unwrap()
+    /// Points the delay timer at the earliest delayed job across all tubes.
+    fn reset_delay_timer(&self) {
+        let next = self
+            .tubes
+            .values()
+            .filter_map(|tube| tube.delay.first())
+            .map(|(ready_at, _)| *ready_at)
+            .min();
+        self.next_delay_tx.send_if_modified(|current| {
+            let changed = *current != next;
+            *current = next;
+            changed
+        });
     }
 
     pub fn new_tube(&mut self, tube: impl ToString) -> &mut Tube {
         self.tubes.entry(tube.to_string()).or_default()
     }
 
-    pub fn new_job(&mut self, tube: String, ttr: u32, pri: u32, data: Bytes) -> u32 {
+    pub fn new_job(&mut self, tube: String, ttr: u32, pri: u32, data: Bytes) -> u64 {
         // TODO: Result with following errors:
         //      - "BURIED <id>\r\n" if the server ran out of memory trying to grow the priority
         //      queue data structure.
@@ -64,129 +209,471 @@ impl Queue {
         //      no longer accepting new jobs. The client should try another server or disconnect
         //      and try again later. To put the server in drain mode, send the SIGUSR1 signal to
         //      the process.
-        let id = self.jobs.len() as u32 + 1;
-        self.jobs.push(Job::new(id, ttr, pri, data));
-        self.queue_job(tube, id);
+        let id = self.next_id();
+        self.insert_job(Job::new(id, tube.clone(), ttr, pri, data, JobState::Ready));
+        self.process_waiters(&tube);
         id
     }
 
-    pub async fn new_delayed_job(
+    pub fn new_delayed_job(
         &mut self,
         tube: String,
         ttr: u32,
         pri: u32,
         delay: u32,
         data: Bytes,
-    ) -> u32 {
-        let id = self.jobs.len() as u32 + 1;
-        self.jobs.push(Job::new(id, ttr, pri, data));
-        self.new_tube(&tube).delay.push(id);
-        self.new_job_tx.send((tube, id, delay)).await.unwrap();
+    ) -> u64 {
+        let id = self.next_id();
+        let mut job = Job::new(id, tube, ttr, pri, data, JobState::Delayed);
+        job.delay = delay;
+        job.deadline_at = Some(Instant::now() + Duration::from_secs(delay as u64));
+        self.insert_job(job);
+        self.reset_delay_timer();
         id
     }
 
-    pub fn queue_job(&mut self, tube: String, id: u32) {
-        // TODO: why error when use `self.job(&id)`?
-        // also just store `&Job`?
-        let job = self.jobs.iter().find(|job| job.id == id).unwrap();
-        let mut tube = self.tubes.entry(tube).or_default();
-        if let Some((idx, _)) = tube.delay.iter().enumerate().find(|(_, job)| job == &&id) {
-            tube.delay.remove(idx);
-        }
-        if tube.ready.is_empty() {
-            tube.ready.push_back(id);
-            tube.smallest_pri = job.pri;
-            return;
+    fn insert_job(&mut self, mut job: Job) {
+        let tube = self.new_tube(&job.tube);
+        tube.total_jobs += 1;
+        tube.insert(&mut job);
+        self.stats.total_jobs += 1;
+        self.jobs.insert(job.id, job);
+    }
+
+    /// Moves every delayed job whose delay has run out into its tube's ready queue. Called when
+    /// the delay timer fires.
+    pub fn promote_delayed(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<_> = self
+            .tubes
+            .values()
+            .flat_map(|tube| tube.delay.range(..=(now, u64::MAX)))
+            .map(|(_, id)| *id)
+            .collect();
+        for id in expired {
+            self.transition(id, JobState::Ready).unwrap();
         }
-        let mut index = match tube.ready.binary_search_by_key(&job.pri, |id| {
-            self.jobs.iter().find(|job| &job.id == id).unwrap().pri
-        }) {
-            Ok(i) => i,
-            Err(i) => i,
-        };
-        while index < tube.ready.len()
-            && self
-                .jobs
+    }
+
+    /// Moves up to `bound` jobs from the tube's buried list into the ready queue, or if there are
+    /// no buried jobs, up to `bound` delayed jobs. Returns the number of jobs kicked.
+    pub fn kick(&mut self, tube: &str, bound: u32) -> u32 {
+        let ids: Vec<_> = match self.tubes.get(tube) {
+            Some(tube) if !tube.buried.is_empty() => tube
+                .buried
                 .iter()
-                .find(|job| job.id == tube.ready[index])
-                .unwrap()
-                .pri
-                == job.pri
-        {
-            index += 1;
+                .take(bound as usize)
+                .map(|(_, id)| *id)
+                .collect(),
+            Some(tube) => tube
+                .delay
+                .iter()
+                .take(bound as usize)
+                .map(|(_, id)| *id)
+                .collect(),
+            None => return 0,
+        };
+        for id in &ids {
+            self.transition(*id, JobState::Ready).unwrap().kicks += 1;
         }
-        tube.ready.insert(index, id);
-        if index == 0 {
-            tube.smallest_pri = job.pri;
+        ids.len() as u32
+    }
+
+    /// Moves a single buried or delayed job into the ready queue.
+    pub fn kick_job(&mut self, id: u64) -> Result<()> {
+        if !self
+            .peek(id)
+            .is_some_and(|job| matches!(job.state, JobState::Buried | JobState::Delayed))
+        {
+            bail!("NOT_FOUND");
         }
+        self.transition(id, JobState::Ready)?.kicks += 1;
+        Ok(())
     }
 
-    pub fn delete_job(&mut self, id: u32) -> bool {
-        if let Some((i, _)) = self.jobs.iter().enumerate().find(|(_, job)| job.id == id) {
-            self.jobs.remove(i);
-            true
-        } else {
-            false
+    /// Deletes a job that is ready, delayed, buried or reserved by `connection_id`.
+    pub fn delete_job(&mut self, id: u64, connection_id: u64) -> Result<()> {
+        match self.jobs.get(&id) {
+            Some(job) if !matches!(job.state, JobState::Reserved(by) if by != connection_id) => {}
+            _ => bail!("NOT_FOUND"),
+        }
+        let job = self.jobs.remove(&id).unwrap();
+        let tube = self.new_tube(&job.tube);
+        tube.remove(&job);
+        tube.cmd_delete += 1;
+        match job.state {
+            JobState::Delayed => self.reset_delay_timer(),
+            JobState::Reserved(connection_id) => self.unmark_reserved(connection_id, id),
+            _ => {}
         }
+        Ok(())
     }
 
-    pub fn reserve_job(&mut self, watch_list: Vec<String>) -> Option<&Job> {
-        let name = watch_list
+    /// Reserves the ready job with the lowest `(pri, id)` across the unpaused tubes in
+    /// `watch_list`.
+    pub fn reserve_job(&mut self, watch_list: Vec<String>, connection_id: u64) -> Option<&Job> {
+        for name in &watch_list {
+            self.new_tube(name);
+        }
+        let (_, id) = watch_list
             .iter()
-            .min_by_key(|&name| self.tubes.get(name).unwrap().smallest_pri)
+            .map(|name| &self.tubes[name])
+            .filter(|tube| !tube.is_paused())
+            .filter_map(|tube| tube.ready.first())
+            .min()
+            .copied()?;
+        let job = self
+            .transition(id, JobState::Reserved(connection_id))
             .unwrap();
-        let tube = self.tubes.get_mut(name).unwrap();
-        tube.ready.pop_front().map(|id| self.job(&id))
-    }
-
-    pub fn reserve_by_id(&mut self, id: u32) -> Option<&Job> {
-        let job = self.jobs.iter().find(|job| job.id == id);
-        for (_, tube) in &mut self.tubes {
-            if let Some((idx, _)) = tube.ready.iter().enumerate().find(|(_, job)| job == &&id) {
-                tube.ready.remove(idx);
-                return job;
-            } else if let Some((idx, _)) =
-                tube.buried.iter().enumerate().find(|(_, job)| job == &&id)
-            {
-                tube.buried.remove(idx);
-                return job;
-            } else if let Some((idx, _)) =
-                tube.delay.iter().enumerate().find(|(_, job)| job == &&id)
+        job.reserves += 1;
+        Some(job)
+    }
+
+    /// Registers a connection that found nothing to reserve in `tubes`. The receiver gets the next
+    /// job reserved for it, unless `remove_waiter` is called first.
+    pub fn add_waiter(&mut self, tubes: Vec<String>, connection_id: u64) -> oneshot::Receiver<Job> {
+        let (tx, rx) = oneshot::channel();
+        for tube in &tubes {
+            self.new_tube(tube).waiters.push_back(connection_id);
+        }
+        self.stats.current_waiting += 1;
+        self.waiters.insert(connection_id, Waiter { tubes, tx });
+        rx
+    }
+
+    /// Stops a connection waiting on a reserve. Returns `false` if it wasn't waiting, because a
+    /// job has already been sent to it.
+    pub fn remove_waiter(&mut self, connection_id: u64) -> bool {
+        self.take_waiter(connection_id).is_some()
+    }
+
+    fn take_waiter(&mut self, connection_id: u64) -> Option<Waiter> {
+        let waiter = self.waiters.remove(&connection_id)?;
+        for tube in &waiter.tubes {
+            self.new_tube(tube)
+                .waiters
+                .retain(|waiting| *waiting != connection_id);
+        }
+        self.stats.current_waiting -= 1;
+        Some(waiter)
+    }
+
+    /// Hands out ready jobs to the connections waiting on `tube`, first come first served.
+    fn process_waiters(&mut self, tube: &str) {
+        loop {
+            let connection_id = match self.tubes.get(tube) {
+                Some(tube) if !tube.is_paused() && !tube.ready.is_empty() => {
+                    match tube.waiters.front() {
+                        Some(connection_id) => *connection_id,
+                        None => return,
+                    }
+                }
+                _ => return,
+            };
+            let waiter = self.take_waiter(connection_id).unwrap();
+            // The reserve was cancelled without removing itself, e.g. the connection went away
+            if waiter.tx.is_closed() {
+                continue;
+            }
+            // There is at least one job in `tube`, though a better one may be in another tube the
+            // connection watches
+            let job = self
+                .reserve_job(waiter.tubes, connection_id)
+                .cloned()
+                .unwrap();
+            if let Err(job) = waiter.tx.send(job) {
+                // The receiver went away after all, so offer the job to everyone else
+                self.transition(job.id, JobState::Ready).unwrap();
+            }
+        }
+    }
+
+    /// Reserves a specific ready, delayed or buried job.
+    pub fn reserve_by_id(&mut self, id: u64, connection_id: u64) -> Option<&Job> {
+        let job = self
+            .transition(id, JobState::Reserved(connection_id))
+            .ok()?;
+        job.reserves += 1;
+        Some(job)
+    }
+
+    /// Puts a job reserved by `connection_id` back into its tube with a new priority.
+    pub fn release_job(&mut self, id: u64, connection_id: u64, pri: u32, delay: u32) -> Result<()> {
+        let job = self.reserved_job_mut(id, connection_id)?;
+        job.pri = pri;
+        job.delay = delay;
+        job.releases += 1;
+        if delay > 0 {
+            self.transition(id, JobState::Delayed)?;
+        } else {
+            self.transition(id, JobState::Ready)?;
+        }
+        Ok(())
+    }
+
+    pub fn peek(&self, id: u64) -> Option<&Job> {
+        self.jobs.get(&id)
+    }
+
+    pub fn peek_ready(&self, tube: &str) -> Option<&Job> {
+        let (_, id) = self.tubes.get(tube)?.ready.first()?;
+        self.peek(*id)
+    }
+
+    /// Returns the delayed job in `tube` that will become ready soonest.
+    pub fn peek_delayed(&self, tube: &str) -> Option<&Job> {
+        let (_, id) = self.tubes.get(tube)?.delay.first()?;
+        self.peek(*id)
+    }
+
+    pub fn peek_buried(&self, tube: &str) -> Option<&Job> {
+        let (_, id) = self.tubes.get(tube)?.buried.first()?;
+        self.peek(*id)
+    }
+
+    /// Puts a job back into its tube after its reservation by `connection_id` ran out of time.
+    pub fn timeout_job(&mut self, id: u64, connection_id: u64) {
+        if let Ok(job) = self.reserved_job_mut(id, connection_id) {
+            // Touched since the timer was set, and the touch has set a new one
+            if job
+                .deadline_at
+                .is_some_and(|deadline_at| deadline_at > Instant::now())
             {
-                tube.delay.remove(idx);
-                return job;
+                return;
             }
+            job.timeouts += 1;
+            self.stats.job_timeouts += 1;
+            self.transition(id, JobState::Ready).unwrap();
+        }
+    }
+
+    /// Puts every job reserved by `connection_id` back into its tube, e.g. when the connection
+    /// closes.
+    pub fn release_all(&mut self, connection_id: u64) {
+        let reserved = self.reserved.remove(&connection_id).unwrap_or_default();
+        for id in reserved {
+            self.transition(id, JobState::Ready).unwrap();
         }
-        None
     }
 
-    pub fn tube_names(&self) -> std::collections::hash_map::Keys<String, Tube> {
+    /// Restarts the TTR of a job reserved by `connection_id`.
+    pub fn touch_job(&mut self, id: u64, connection_id: u64) -> Result<&Job> {
+        let job = self.reserved_job_mut(id, connection_id)?;
+        job.deadline_at = Some(Instant::now() + Duration::from_secs(job.ttr as u64));
+        Ok(job)
+    }
+
+    /// Moves a job reserved by `connection_id` to the back of its tube's buried list.
+    pub fn bury_job(&mut self, id: u64, connection_id: u64, pri: u32) -> Result<()> {
+        let job = self.reserved_job_mut(id, connection_id)?;
+        job.pri = pri;
+        job.buries += 1;
+        self.transition(id, JobState::Buried)?;
+        Ok(())
+    }
+
+    pub fn start_using(&mut self, tube: &str) {
+        self.new_tube(tube).using += 1;
+    }
+
+    pub fn stop_using(&mut self, tube: &str) {
+        self.new_tube(tube).using -= 1;
+    }
+
+    pub fn watch_tube(&mut self, tube: &str) {
+        self.new_tube(tube).watching += 1;
+    }
+
+    pub fn ignore_tube(&mut self, tube: &str) {
+        self.new_tube(tube).watching -= 1;
+    }
+
+    pub fn tube_stats(&self, name: &str) -> Option<TubeStats> {
+        let tube = self.tubes.get(name)?;
+        Some(TubeStats {
+            urgent: tube.ready.range(..(settings::URGENT_THRESHOLD, 0)).count(),
+            ready: tube.ready.len(),
+            reserved: tube.reserved,
+            delayed: tube.delay.len(),
+            buried: tube.buried.len(),
+            total_jobs: tube.total_jobs,
+            using: tube.using,
+            watching: tube.watching,
+            waiting: tube.waiters.len() as u32,
+            cmd_delete: tube.cmd_delete,
+            cmd_pause_tube: tube.cmd_pause_tube,
+            pause: tube.pause,
+            pause_time_left: tube.pause_until.map_or(0, |pause_until| {
+                pause_until
+                    .saturating_duration_since(Instant::now())
+                    .as_secs()
+            }),
+        })
+    }
+
+    /// Stops jobs in the tube from being reserved for `delay` seconds. Returns `false` if the tube
+    /// doesn't exist.
+    pub fn pause_tube(&mut self, tube: &str, delay: u32) -> bool {
+        match self.tubes.get_mut(tube) {
+            Some(tube) => {
+                tube.cmd_pause_tube += 1;
+                tube.pause = delay;
+                tube.pause_until = Some(Instant::now() + Duration::from_secs(delay as u64));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Clears the tube's pause once it has expired.
+    pub fn unpause_tube(&mut self, name: &str) {
+        if let Some(tube) = self.tubes.get_mut(name) {
+            if !tube.is_paused() {
+                tube.pause = 0;
+                tube.pause_until = None;
+            }
+        }
+        self.process_waiters(name);
+    }
+
+    pub fn tube_names(&self) -> std::collections::hash_map::Keys<'_, String, Tube> {
         self.tubes.keys()
     }
 }
 
+impl Tube {
+    /// Adds a job to the list for its current state.
+    fn insert(&mut self, job: &mut Job) {
+        match job.state {
+            JobState::Ready => {
+                self.ready.insert((job.pri, job.id));
+            }
+            JobState::Delayed => {
+                self.delay.insert((job.deadline_at.unwrap(), job.id));
+            }
+            JobState::Buried => {
+                self.last_bury_seq += 1;
+                job.bury_seq = self.last_bury_seq;
+                self.buried.insert((job.bury_seq, job.id));
+            }
+            JobState::Reserved(_) => self.reserved += 1,
+        }
+    }
+
+    /// Takes a job out of the list for its current state.
+    fn remove(&mut self, job: &Job) {
+        match job.state {
+            JobState::Ready => {
+                self.ready.remove(&(job.pri, job.id));
+            }
+            JobState::Delayed => {
+                self.delay.remove(&(job.deadline_at.unwrap(), job.id));
+            }
+            JobState::Buried => {
+                self.buried.remove(&(job.bury_seq, job.id));
+            }
+            JobState::Reserved(_) => self.reserved -= 1,
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.pause_until
+            .is_some_and(|pause_until| pause_until > Instant::now())
+    }
+}
+
 impl Job {
-    pub fn new(id: u32, ttr: u32, pri: u32, data: Bytes) -> Self {
+    pub fn new(id: u64, tube: String, ttr: u32, pri: u32, data: Bytes, state: JobState) -> Self {
         let ttr = if ttr == 0 { 1 } else { ttr };
-        Self { id, ttr, pri, data }
+        Self {
+            id,
+            tube,
+            ttr,
+            pri,
+            data,
+            state,
+            created_at: Instant::now(),
+            delay: 0,
+            deadline_at: None,
+            reserves: 0,
+            timeouts: 0,
+            releases: 0,
+            buries: 0,
+            kicks: 0,
+            bury_seq: 0,
+        }
+    }
+
+    /// Moves the job to a new state, returning the old one. Fails with `NOT_FOUND` if the
+    /// transition isn't allowed.
+    fn transition(&mut self, to: JobState) -> Result<JobState> {
+        if !self.state.can_become(to) {
+            bail!("NOT_FOUND");
+        }
+        self.deadline_at = match to {
+            JobState::Reserved(_) => Some(Instant::now() + Duration::from_secs(self.ttr as u64)),
+            JobState::Delayed => Some(Instant::now() + Duration::from_secs(self.delay as u64)),
+            _ => None,
+        };
+        Ok(std::mem::replace(&mut self.state, to))
+    }
+
+    /// Seconds until a reserved job times out or a delayed job becomes ready
+    pub fn time_left(&self) -> u64 {
+        self.deadline_at.map_or(0, |deadline_at| {
+            deadline_at
+                .saturating_duration_since(Instant::now())
+                .as_secs()
+        })
     }
 }
 
+impl JobState {
+    fn can_become(&self, to: JobState) -> bool {
+        matches!(
+            (self, to),
+            (JobState::Ready, JobState::Reserved(_))
+                | (
+                    JobState::Delayed | JobState::Buried,
+                    JobState::Ready | JobState::Reserved(_)
+                )
+                | (
+                    JobState::Reserved(_),
+                    JobState::Ready | JobState::Delayed | JobState::Buried
+                )
+        )
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Ready => "ready",
+            JobState::Delayed => "delayed",
+            JobState::Reserved(_) => "reserved",
+            JobState::Buried => "buried",
+        }
+    }
+}
+
+/// A single timer for every delayed job. It sleeps until the earliest one is due, then asks for
+/// `Queue::promote_delayed` to be run.
 async fn watch_delayed_jobs(
-    mut new_job_rx: mpsc::Receiver<(String, u32, u32)>,
-    ready_job_tx: mpsc::Sender<(String, u32)>,
+    mut next_delay_rx: watch::Receiver<Option<Instant>>,
+    ready_job_tx: mpsc::Sender<()>,
 ) {
-    let mut jobs = FuturesUnordered::new();
     loop {
+        let next = *next_delay_rx.borrow_and_update();
         select! {
-            Some((tube, id, delay)) = new_job_rx.recv() => {
-                jobs.push(tokio::spawn(async move {
-                    sleep(Duration::from_secs(delay as u64)).await;
-                    (tube, id)
-                }));
+            changed = next_delay_rx.changed() => {
+                // The queue has been dropped
+                if changed.is_err() {
+                    break;
+                }
             }
-            Some(Ok(job)) = jobs.next() => {
-                ready_job_tx.send(job).await.unwrap();
+            _ = sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                // The next deadline only moves once the due jobs have been promoted
+                if ready_job_tx.send(()).await.is_err() || next_delay_rx.changed().await.is_err() {
+                    break;
+                }
             }
         }
     }
@@ -197,6 +684,10 @@ mod tests {
 
     use super::*;
 
+    fn ready_ids(queue: &Queue, tube: &str) -> Vec<u64> {
+        queue.tubes[tube].ready.iter().map(|(_, id)| *id).collect()
+    }
+
     #[tokio::test]
     async fn tube_ready() {
         let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
@@ -205,10 +696,7 @@ mod tests {
         queue.new_job("default".to_string(), 0, 0, Bytes::new());
         queue.new_job("default".to_string(), 0, 10, Bytes::new());
         queue.new_job("default".to_string(), 0, 1, Bytes::new());
-        assert_eq!(
-            queue.tubes.get("default").unwrap().ready,
-            VecDeque::from([1, 2, 4, 3])
-        );
+        assert_eq!(ready_ids(&queue, "default"), vec![1, 2, 4, 3]);
     }
 
     #[tokio::test]
@@ -216,43 +704,386 @@ mod tests {
         let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
         let mut queue = Queue::new(ready_job_tx);
         queue.new_job("default".to_string(), 0, 100, Bytes::new());
-        assert_eq!(queue.tubes.get("default").unwrap().smallest_pri, 100);
+        assert_eq!(queue.peek_ready("default").unwrap().pri, 100);
         queue.new_job("default".to_string(), 0, 1000, Bytes::new());
-        assert_eq!(queue.tubes.get("default").unwrap().smallest_pri, 100);
+        assert_eq!(queue.peek_ready("default").unwrap().pri, 100);
         queue.new_job("default".to_string(), 0, 10, Bytes::new());
-        assert_eq!(queue.tubes.get("default").unwrap().smallest_pri, 10);
+        assert_eq!(queue.peek_ready("default").unwrap().pri, 10);
         queue.new_job("default".to_string(), 0, 1, Bytes::new());
-        assert_eq!(queue.tubes.get("default").unwrap().smallest_pri, 1);
+        assert_eq!(queue.peek_ready("default").unwrap().pri, 1);
     }
 
     #[tokio::test]
-    #[ignore]
-    async fn delay_job() {
-        use std::sync::{Arc, Mutex};
+    async fn release_job() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        queue.new_job("default".to_string(), 0, 5, Bytes::new());
+        assert_eq!(
+            queue
+                .reserve_job(vec!["default".to_string()], 1)
+                .unwrap()
+                .id,
+            1
+        );
+        assert!(queue.release_job(1, 2, 10, 0).is_err());
+        assert!(queue.release_job(1, 1, 10, 0).is_ok());
+        assert!(queue.release_job(1, 1, 10, 0).is_err());
+        assert_eq!(ready_ids(&queue, "default"), vec![2, 1]);
+        assert_eq!(queue.jobs[&1].pri, 10);
+    }
 
-        let (ready_job_tx, mut ready_job_rx) = mpsc::channel(100);
-        let done = Arc::new(Mutex::new(false));
+    #[tokio::test]
+    async fn bury_job() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        queue.reserve_job(vec!["default".to_string()], 1);
+        queue.reserve_job(vec!["default".to_string()], 1);
+        assert!(queue.bury_job(2, 2, 10).is_err());
+        assert!(queue.bury_job(2, 1, 10).is_ok());
+        assert!(queue.bury_job(1, 1, 10).is_ok());
+        assert!(queue.bury_job(1, 1, 10).is_err());
+        let tube = queue.tubes.get("default").unwrap();
+        assert_eq!(
+            tube.buried.iter().map(|(_, id)| *id).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        assert!(tube.ready.is_empty());
 
-        let done1 = done.clone();
-        tokio::spawn(async move {
-            if let Some(_) = ready_job_rx.recv().await {
-                let mut done = done1.lock().unwrap();
-                *done = true;
-            }
-        });
+        // Taking jobs out of the middle of the list keeps the rest in order
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        queue.reserve_job(vec!["default".to_string()], 1);
+        queue.bury_job(3, 1, 10).unwrap();
+        queue.delete_job(1, 1).unwrap();
+        assert_eq!(queue.peek_buried("default").unwrap().id, 2);
+        queue.kick_job(2).unwrap();
+        assert_eq!(queue.peek_buried("default").unwrap().id, 3);
+        queue.reserve_by_id(2, 1).unwrap();
+        queue.bury_job(2, 1, 10).unwrap();
+        assert_eq!(queue.kick("default", 1), 1);
+        assert_eq!(queue.peek_buried("default").unwrap().id, 2);
+    }
 
+    #[tokio::test]
+    async fn peek() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
         let mut queue = Queue::new(ready_job_tx);
-        queue
-            .new_delayed_job("default".to_string(), 0, 0, 1, Bytes::new())
-            .await;
-        {
-            let done = done.lock().unwrap();
-            assert!(!*done);
+        queue.new_job("default".to_string(), 0, 10, Bytes::new());
+        queue.new_job("default".to_string(), 0, 5, Bytes::new());
+        queue.new_delayed_job("default".to_string(), 0, 0, 20, Bytes::new());
+        queue.new_delayed_job("default".to_string(), 0, 0, 10, Bytes::new());
+        assert_eq!(queue.peek(1).unwrap().id, 1);
+        assert!(queue.peek(5).is_none());
+        assert_eq!(queue.peek_ready("default").unwrap().id, 2);
+        assert_eq!(queue.peek_delayed("default").unwrap().id, 4);
+        assert!(queue.peek_buried("default").is_none());
+        assert!(queue.peek_ready("other").is_none());
+    }
+
+    #[tokio::test]
+    async fn kick() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        for _ in 0..3 {
+            queue.new_job("default".to_string(), 0, 0, Bytes::new());
+            queue.reserve_job(vec!["default".to_string()], 1);
         }
-        sleep(Duration::from_secs_f32(1.1)).await;
-        {
-            let done = done.lock().unwrap();
-            assert!(*done);
+        queue.new_delayed_job("default".to_string(), 0, 0, 10, Bytes::new());
+        queue.bury_job(1, 1, 0).unwrap();
+        queue.bury_job(2, 1, 0).unwrap();
+        assert_eq!(queue.kick("default", 1), 1);
+        assert_eq!(queue.kick("default", 10), 1);
+        assert_eq!(queue.kick("default", 10), 1);
+        assert_eq!(queue.kick("default", 10), 0);
+        assert_eq!(ready_ids(&queue, "default"), vec![1, 2, 4]);
+
+        assert!(queue.kick_job(3).is_err());
+        queue.bury_job(3, 1, 0).unwrap();
+        assert!(queue.kick_job(3).is_ok());
+        assert!(queue.kick_job(3).is_err());
+        queue.promote_delayed();
+        assert_eq!(ready_ids(&queue, "default"), vec![1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn job_stats() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        assert_eq!(queue.peek(1).unwrap().state, JobState::Ready);
+        queue.reserve_job(vec!["default".to_string()], 1);
+        assert_eq!(queue.peek(1).unwrap().state, JobState::Reserved(1));
+        queue.release_job(1, 1, 0, 10).unwrap();
+        assert_eq!(queue.peek(1).unwrap().state, JobState::Delayed);
+        queue.kick_job(1).unwrap();
+        queue.reserve_job(vec!["default".to_string()], 1);
+        queue.bury_job(1, 1, 0).unwrap();
+        assert_eq!(queue.peek(1).unwrap().state, JobState::Buried);
+
+        let job = queue.peek(1).unwrap();
+        assert_eq!(job.delay, 10);
+        assert_eq!(job.reserves, 2);
+        assert_eq!(job.releases, 1);
+        assert_eq!(job.kicks, 1);
+        assert_eq!(job.buries, 1);
+        assert_eq!(job.time_left(), 0);
+    }
+
+    #[tokio::test]
+    async fn tube_stats() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        queue.new_job("default".to_string(), 0, 2000, Bytes::new());
+        queue.new_job("default".to_string(), 0, 5, Bytes::new());
+        queue.new_job("other".to_string(), 0, 0, Bytes::new());
+        queue.reserve_job(vec!["default".to_string()], 1);
+        queue.reserve_job(vec!["default".to_string()], 1);
+        queue.delete_job(3, 1).unwrap();
+        queue.watch_tube("default");
+        queue.start_using("other");
+
+        let stats = queue.tube_stats("default").unwrap();
+        assert_eq!(stats.urgent, 0);
+        assert_eq!(stats.ready, 1);
+        assert_eq!(stats.reserved, 1);
+        assert_eq!(stats.total_jobs, 3);
+        assert_eq!(stats.cmd_delete, 1);
+        assert_eq!(stats.watching, 1);
+        let stats = queue.tube_stats("other").unwrap();
+        assert_eq!(stats.urgent, 1);
+        assert_eq!(stats.using, 1);
+        assert!(queue.tube_stats("missing").is_none());
+
+        queue.release_job(1, 1, 0, 0).unwrap();
+        assert_eq!(queue.tube_stats("default").unwrap().reserved, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pause_tube() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        assert!(!queue.pause_tube("other", 10));
+        assert!(queue.pause_tube("default", 10));
+        assert!(queue.reserve_job(vec!["default".to_string()], 1).is_none());
+        assert_eq!(queue.tube_stats("default").unwrap().pause_time_left, 10);
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        queue.unpause_tube("default");
+        assert_eq!(queue.tube_stats("default").unwrap().pause, 0);
+        assert_eq!(
+            queue
+                .reserve_job(vec!["default".to_string()], 1)
+                .unwrap()
+                .id,
+            1
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout_job() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        queue.reserve_job(vec!["default".to_string()], 1);
+        queue.timeout_job(1, 2);
+        assert!(queue.reserve_job(vec!["default".to_string()], 2).is_none());
+        queue.timeout_job(1, 1);
+        assert_eq!(queue.peek(1).unwrap().state, JobState::Reserved(1));
+        tokio::time::advance(Duration::from_secs(1)).await;
+        queue.timeout_job(1, 1);
+        assert_eq!(queue.peek(1).unwrap().state, JobState::Ready);
+        assert_eq!(queue.peek(1).unwrap().timeouts, 1);
+        assert_eq!(queue.stats.job_timeouts, 1);
+        assert_eq!(
+            queue
+                .reserve_job(vec!["default".to_string()], 2)
+                .unwrap()
+                .state,
+            JobState::Reserved(2)
+        );
+
+        // A timer that fires just after a touch is ignored
+        queue.new_job("default".to_string(), 60, 0, Bytes::new());
+        queue.reserve_job(vec!["default".to_string()], 1);
+        queue.touch_job(2, 1).unwrap();
+        queue.timeout_job(2, 1);
+        assert_eq!(queue.peek(2).unwrap().state, JobState::Reserved(1));
+        assert_eq!(queue.stats.job_timeouts, 1);
+    }
+
+    #[tokio::test]
+    async fn release_all() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        queue.new_job("other".to_string(), 0, 0, Bytes::new());
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        queue.reserve_job(vec!["default".to_string()], 1);
+        queue.reserve_job(vec!["other".to_string()], 1);
+        queue.reserve_job(vec!["default".to_string()], 2);
+        queue.reserve_job(vec!["default".to_string()], 1);
+        queue.delete_job(4, 1).unwrap();
+        queue.release_all(1);
+        assert_eq!(ready_ids(&queue, "default"), vec![1]);
+        assert_eq!(ready_ids(&queue, "other"), vec![2]);
+        assert_eq!(queue.peek(3).unwrap().state, JobState::Reserved(2));
+        assert_eq!(queue.peek(1).unwrap().timeouts, 0);
+        assert!(!queue.reserved.contains_key(&1));
+        assert_eq!(queue.reserved[&2], HashSet::from([3]));
+    }
+
+    #[tokio::test]
+    async fn illegal_transitions() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        assert!(queue.kick_job(1).is_err());
+        assert!(queue.release_job(1, 1, 0, 0).is_err());
+        assert!(queue.touch_job(1, 1).is_err());
+        assert!(queue.reserve_by_id(1, 1).is_some());
+        assert!(queue.reserve_by_id(1, 2).is_none());
+        assert!(queue.kick_job(1).is_err());
+        queue.bury_job(1, 1, 0).unwrap();
+        assert!(queue.bury_job(1, 1, 0).is_err());
+        assert!(queue.release_job(1, 1, 0, 0).is_err());
+        assert_eq!(queue.peek(1).unwrap().state, JobState::Buried);
+        assert_eq!(queue.tube_stats("default").unwrap().buried, 1);
+    }
+
+    #[tokio::test]
+    async fn delete_job() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        for _ in 0..4 {
+            queue.new_job("default".to_string(), 0, 0, Bytes::new());
         }
+        queue.new_delayed_job("default".to_string(), 0, 0, 10, Bytes::new());
+        queue.reserve_job(vec!["default".to_string()], 1);
+        queue.reserve_job(vec!["default".to_string()], 2);
+        queue.reserve_job(vec!["default".to_string()], 1);
+        queue.bury_job(3, 1, 0).unwrap();
+
+        assert!(queue.delete_job(1, 2).is_err());
+        queue.delete_job(1, 1).unwrap();
+        assert!(queue.delete_job(1, 1).is_err());
+        queue.delete_job(3, 1).unwrap();
+        queue.delete_job(4, 1).unwrap();
+        queue.delete_job(5, 1).unwrap();
+        assert!(queue.reserve_job(vec!["default".to_string()], 1).is_none());
+        queue.promote_delayed();
+
+        let stats = queue.tube_stats("default").unwrap();
+        assert_eq!(stats.ready + stats.delayed + stats.buried, 0);
+        assert_eq!(stats.reserved, 1);
+        assert_eq!(stats.cmd_delete, 4);
+    }
+
+    #[tokio::test]
+    async fn ids_not_reused() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        assert_eq!(queue.new_job("default".to_string(), 0, 0, Bytes::new()), 1);
+        assert_eq!(queue.new_job("default".to_string(), 0, 0, Bytes::new()), 2);
+        queue.delete_job(2, 1).unwrap();
+        queue.delete_job(1, 1).unwrap();
+        assert_eq!(queue.new_job("default".to_string(), 0, 0, Bytes::new()), 3);
+        assert_eq!(
+            queue.new_delayed_job("default".to_string(), 0, 0, 1, Bytes::new()),
+            4
+        );
+    }
+
+    #[tokio::test]
+    async fn reserve_multiple_tubes() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        let watch_list = vec![
+            "empty".to_string(),
+            "default".to_string(),
+            "other".to_string(),
+            "paused".to_string(),
+        ];
+        queue.new_job("default".to_string(), 0, 10, Bytes::new());
+        queue.new_job("other".to_string(), 0, 5, Bytes::new());
+        queue.new_job("other".to_string(), 0, 10, Bytes::new());
+        queue.new_job("paused".to_string(), 0, 0, Bytes::new());
+        queue.pause_tube("paused", 10);
+
+        let reserved: Vec<_> = (0..3)
+            .map(|_| queue.reserve_job(watch_list.clone(), 1).unwrap().id)
+            .collect();
+        assert_eq!(reserved, vec![2, 1, 3]);
+        assert!(queue.reserve_job(watch_list, 1).is_none());
+        assert!(queue.tube_stats("empty").is_some());
+    }
+
+    #[tokio::test]
+    async fn waiters() {
+        let (ready_job_tx, _ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        let mut first = queue.add_waiter(vec!["default".to_string()], 1);
+        let mut other = queue.add_waiter(vec!["other".to_string()], 2);
+        let mut second = queue.add_waiter(vec!["default".to_string()], 3);
+        let gone = queue.add_waiter(vec!["default".to_string()], 4);
+        drop(gone);
+        assert_eq!(queue.stats.current_waiting, 4);
+
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        assert_eq!(first.try_recv().unwrap().state, JobState::Reserved(1));
+        assert!(second.try_recv().is_err());
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        assert_eq!(second.try_recv().unwrap().id, 2);
+        assert!(other.try_recv().is_err());
+        assert_eq!(queue.stats.current_waiting, 2);
+
+        // The dropped waiter is cleaned up rather than handed a job
+        queue.new_job("default".to_string(), 0, 0, Bytes::new());
+        assert_eq!(ready_ids(&queue, "default"), vec![3]);
+        assert_eq!(queue.stats.current_waiting, 1);
+        assert!(queue.remove_waiter(2));
+        assert!(!queue.remove_waiter(2));
+        assert_eq!(queue.tube_stats("other").unwrap().waiting, 0);
+
+        // A connection watching several tubes leaves all of their queues once it gets a job
+        let watch_list = vec!["a".to_string(), "b".to_string()];
+        let mut both = queue.add_waiter(watch_list.clone(), 5);
+        let mut b = queue.add_waiter(vec!["b".to_string()], 6);
+        assert_eq!(queue.tube_stats("b").unwrap().waiting, 2);
+        queue.new_job("a".to_string(), 0, 0, Bytes::new());
+        assert_eq!(both.try_recv().unwrap().id, 4);
+        assert_eq!(queue.tube_stats("b").unwrap().waiting, 1);
+        queue.new_job("b".to_string(), 0, 0, Bytes::new());
+        assert_eq!(b.try_recv().unwrap().id, 5);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delay_job() {
+        let (ready_job_tx, mut ready_job_rx) = mpsc::channel(100);
+        let mut queue = Queue::new(ready_job_tx);
+        queue.new_delayed_job("default".to_string(), 0, 0, 20, Bytes::new());
+        queue.new_delayed_job("default".to_string(), 0, 0, 10, Bytes::new());
+        queue.new_delayed_job("default".to_string(), 0, 0, 5, Bytes::new());
+        queue.new_delayed_job("default".to_string(), 0, 0, 30, Bytes::new());
+        queue.delete_job(3, 1).unwrap();
+        queue.kick_job(4).unwrap();
+
+        ready_job_rx.recv().await.unwrap();
+        queue.promote_delayed();
+        assert_eq!(ready_ids(&queue, "default"), vec![2, 4]);
+        assert_eq!(queue.peek_delayed("default").unwrap().time_left(), 10);
+
+        ready_job_rx.recv().await.unwrap();
+        queue.promote_delayed();
+        assert_eq!(ready_ids(&queue, "default"), vec![1, 2, 4]);
+        assert!(
+            tokio::time::timeout(Duration::from_secs(60), ready_job_rx.recv())
+                .await
+                .is_err()
+        );
     }
 }
@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, Mutex},
+};
+
+use crate::{config::Config, connection::Connection, queue::Queue};
+
+/// Serves clients on `listener` for as long as the returned future is polled.
+pub async fn run(listener: TcpListener, config: Config) {
+    let (ready_job_tx, ready_job_rx) = mpsc::channel(100);
+    let mut queue = Queue::new(ready_job_tx);
+    queue.stats.max_job_size = config.max_job_size;
+    queue.stats.binlog_max_size = config.binlog_size;
+    let queue = Arc::new(Mutex::new(queue));
+
+    tokio::spawn(watch_delay_jobs(queue.clone(), ready_job_rx));
+
+    loop {
+        let (socket, _) = listener.accept().await.unwrap();
+        let queue = queue.clone();
+
+        tokio::spawn(async move {
+            let mut connection = Connection::new(socket, queue.clone(), config.max_job_size);
+            connection.run(queue).await;
+        });
+    }
+}
+
+/// Promotes delayed jobs each time the queue's delay timer fires, until the queue is dropped.
+async fn watch_delay_jobs(queue: Arc<Mutex<Queue>>, mut ready_job_rx: mpsc::Receiver<()>) {
+    while let Some(()) = ready_job_rx.recv().await {
+        queue.lock().await.promote_delayed();
+    }
+}
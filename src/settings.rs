@@ -1,2 +1,17 @@
-// TODO: user customization
-pub const MAX_JOB_SIZE: u32 = 2_u32.pow(16) - 1;
+/// Used unless `max-job-size` is configured
+pub const DEFAULT_MAX_JOB_SIZE: u32 = 2_u32.pow(16) - 1;
+
+/// beanstalkd's port, so either server can be swapped in
+pub const DEFAULT_PORT: u16 = 11300;
+
+/// Size of each binlog file unless `binlog-size` is configured
+pub const DEFAULT_BINLOG_SIZE: u32 = 10 * 1024 * 1024;
+
+/// Longest command line accepted, including the CRLF, as in beanstalkd
+pub const MAX_LINE_SIZE: usize = 224;
+
+/// Jobs with a priority below this are counted as urgent in stats
+pub const URGENT_THRESHOLD: u32 = 1024;
+
+/// Seconds before a reservation times out during which `reserve` returns `DEADLINE_SOON`
+pub const SAFETY_MARGIN: u64 = 1;
@@ -0,0 +1,108 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    ffi::CStr,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use crate::settings;
+
+/// Server-wide counters reported by the `stats` command.
+pub struct Stats {
+    started_at: Instant,
+    id: String,
+    hostname: String,
+    commands: HashMap<&'static str, u64>,
+
+    /// Configured rather than counted, but reported alongside everything else
+    pub max_job_size: u32,
+    pub binlog_max_size: u32,
+    pub job_timeouts: u64,
+    pub total_jobs: u64,
+    pub current_connections: u64,
+    pub total_connections: u64,
+    pub current_producers: u64,
+    pub current_workers: u64,
+    pub current_waiting: u64,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            id: server_id(),
+            hostname: hostname(),
+            commands: HashMap::new(),
+            max_job_size: settings::DEFAULT_MAX_JOB_SIZE,
+            binlog_max_size: settings::DEFAULT_BINLOG_SIZE,
+            job_timeouts: 0,
+            total_jobs: 0,
+            current_connections: 0,
+            total_connections: 0,
+            current_producers: 0,
+            current_workers: 0,
+            current_waiting: 0,
+        }
+    }
+
+    pub fn count_command(&mut self, name: &'static str) {
+        *self.commands.entry(name).or_default() += 1;
+    }
+
+    pub fn commands(&self, name: &str) -> u64 {
+        self.commands.get(name).copied().unwrap_or_default()
+    }
+
+    pub fn connection_opened(&mut self) {
+        self.current_connections += 1;
+        self.total_connections += 1;
+    }
+
+    pub fn uptime(&self) -> u64 {
+        self.started_at.elapsed().as_secs()
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+}
+
+/// User and system CPU time used by the process
+pub fn rusage() -> (Duration, Duration) {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: `getrusage` fully initializes `usage` when it returns 0.
+    let usage = unsafe {
+        if libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) != 0 {
+            return (Duration::ZERO, Duration::ZERO);
+        }
+        usage.assume_init()
+    };
+    let duration =
+        |time: libc::timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+    (duration(usage.ru_utime), duration(usage.ru_stime))
+}
+
+/// A random id so clients can tell servers apart, like the one beanstalkd generates on startup
+fn server_id() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    format!("{:016x}", hasher.finish())
+}
+
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for `buf.len()` bytes and the last byte is never written, so
+    // the result is always nul-terminated.
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len() - 1) } != 0 {
+        return String::new();
+    }
+    CStr::from_bytes_until_nul(&buf)
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
@@ -0,0 +1,82 @@
+// Not every test uses every helper
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+
+use beanstalkrs::{config::Config, server};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+/// Starts a server on a free port.
+pub async fn spawn_server() -> SocketAddr {
+    spawn_server_with(Config::default()).await
+}
+
+pub async fn spawn_server_with(config: Config) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::run(listener, config));
+    addr
+}
+
+pub struct Client {
+    stream: BufReader<TcpStream>,
+}
+
+impl Client {
+    pub async fn connect(addr: SocketAddr) -> Self {
+        Self {
+            stream: BufReader::new(TcpStream::connect(addr).await.unwrap()),
+        }
+    }
+
+    /// Sends a command and returns the first line of the response.
+    pub async fn send(&mut self, cmd: &str) -> String {
+        self.write(cmd).await;
+        self.read_line().await
+    }
+
+    /// Sends a command without waiting for a response.
+    pub async fn write(&mut self, cmd: &str) {
+        self.stream.write_all(cmd.as_bytes()).await.unwrap();
+    }
+
+    pub async fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        self.stream.read_line(&mut line).await.unwrap();
+        line.trim_end().to_string()
+    }
+
+    /// Sends a reserve command and returns the reserved job's id, or the response if nothing was
+    /// reserved.
+    pub async fn reserve(&mut self, cmd: &str) -> Result<u64, String> {
+        let response = self.send(cmd).await;
+        match response.split(' ').collect::<Vec<_>>()[..] {
+            ["RESERVED", id, _] => {
+                self.read_line().await;
+                Ok(id.parse().unwrap())
+            }
+            _ => Err(response),
+        }
+    }
+
+    /// Sends `stats` and returns the value of the named field.
+    pub async fn stat(&mut self, name: &str) -> String {
+        assert!(self.send("stats\r\n").await.starts_with("OK "));
+        let mut value = None;
+        loop {
+            let line = self.read_line().await;
+            if let Some(v) = line.strip_prefix(&format!("{name}: ")) {
+                value = Some(v.to_string());
+            }
+            if line.starts_with("platform") {
+                break;
+            }
+        }
+        // The CRLF after the body
+        self.read_line().await;
+        value.unwrap()
+    }
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use beanstalkrs::config::Config;
+use common::{spawn_server_with, Client};
+
+#[tokio::test]
+async fn max_job_size() {
+    let mut client = Client::connect(
+        spawn_server_with(Config {
+            max_job_size: 5,
+            ..Config::default()
+        })
+        .await,
+    )
+    .await;
+    assert_eq!(
+        client.send("put 0 0 60 10\r\n0123456789\r\n").await,
+        "JOB_TOO_BIG"
+    );
+    assert_eq!(client.send("put 0 0 60 5\r\nhello\r\n").await, "INSERTED 1");
+
+    assert!(client.send("stats\r\n").await.starts_with("OK "));
+    let mut found = false;
+    loop {
+        match client.read_line().await.as_str() {
+            "max-job-size: 5" => found = true,
+            line if line.starts_with("platform") => break,
+            _ => {}
+        }
+    }
+    assert!(found);
+}
+
+#[tokio::test]
+async fn binlog_size() {
+    let mut client = Client::connect(
+        spawn_server_with(Config {
+            binlog_size: 1000,
+            ..Config::default()
+        })
+        .await,
+    )
+    .await;
+    assert_eq!(client.stat("binlog-max-size").await, "1000");
+}
@@ -0,0 +1,24 @@
+mod common;
+
+use common::{spawn_server, Client};
+
+#[tokio::test]
+async fn delayed_jobs_become_ready() {
+    let mut client = Client::connect(spawn_server().await).await;
+    for delay in [1, 1, 2, 1, 60] {
+        client.send(&format!("put 0 {delay} 60 1\r\nx\r\n")).await;
+    }
+    assert_eq!(client.send("delete 4\r\n").await, "DELETED");
+    assert_eq!(client.send("kick-job 5\r\n").await, "KICKED");
+
+    let mut reserved = Vec::new();
+    for _ in 0..4 {
+        reserved.push(client.reserve("reserve-with-timeout 5\r\n").await.unwrap());
+    }
+    reserved.sort();
+    assert_eq!(reserved, vec![1, 2, 3, 5]);
+    assert_eq!(
+        client.reserve("reserve-with-timeout 2\r\n").await,
+        Err("TIMED_OUT".to_string())
+    );
+}
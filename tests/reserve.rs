@@ -0,0 +1,119 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{spawn_server, Client};
+use tokio::time::sleep;
+
+#[tokio::test]
+async fn reserve_waits_for_a_job() {
+    let addr = spawn_server().await;
+    let mut worker = Client::connect(addr).await;
+    let mut producer = Client::connect(addr).await;
+
+    let reserve = tokio::spawn(async move { worker.reserve("reserve\r\n").await });
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(!reserve.is_finished());
+    producer.send("put 0 0 60 1\r\nx\r\n").await;
+    assert_eq!(reserve.await.unwrap(), Ok(1));
+}
+
+#[tokio::test]
+async fn zero_timeout_does_not_block() {
+    let mut client = Client::connect(spawn_server().await).await;
+    let start = Instant::now();
+    assert_eq!(
+        client.reserve("reserve-with-timeout 0\r\n").await,
+        Err("TIMED_OUT".to_string())
+    );
+    assert!(start.elapsed() < Duration::from_millis(500));
+    client.send("put 0 0 60 1\r\nx\r\n").await;
+    assert_eq!(client.reserve("reserve-with-timeout 0\r\n").await, Ok(1));
+}
+
+#[tokio::test]
+async fn deadline_soon() {
+    let mut client = Client::connect(spawn_server().await).await;
+    client.send("put 0 0 2 1\r\nx\r\n").await;
+    assert_eq!(client.reserve("reserve\r\n").await, Ok(1));
+
+    // Waits until the job enters its safety margin
+    let start = Instant::now();
+    assert_eq!(
+        client.reserve("reserve\r\n").await,
+        Err("DEADLINE_SOON".to_string())
+    );
+    assert!(start.elapsed() >= Duration::from_millis(900));
+    // And then answers straight away
+    assert_eq!(
+        client.reserve("reserve-with-timeout 10\r\n").await,
+        Err("DEADLINE_SOON".to_string())
+    );
+
+    assert_eq!(client.send("touch 1\r\n").await, "TOUCHED");
+    assert_eq!(
+        client.reserve("reserve-with-timeout 0\r\n").await,
+        Err("TIMED_OUT".to_string())
+    );
+}
+
+#[tokio::test]
+async fn reservation_times_out() {
+    let addr = spawn_server().await;
+    let mut first = Client::connect(addr).await;
+    let mut second = Client::connect(addr).await;
+    first.send("put 0 0 1 1\r\nx\r\n").await;
+    assert_eq!(first.reserve("reserve\r\n").await, Ok(1));
+    assert_eq!(second.reserve("reserve-with-timeout 3\r\n").await, Ok(1));
+    assert_eq!(first.send("delete 1\r\n").await, "NOT_FOUND");
+}
+
+#[tokio::test]
+async fn disconnect_while_waiting() {
+    let addr = spawn_server().await;
+    let mut waiting = Client::connect(addr).await;
+    let mut client = Client::connect(addr).await;
+    waiting.write("reserve\r\n").await;
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(client.stat("current-waiting").await, "1");
+
+    drop(waiting);
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(client.stat("current-waiting").await, "0");
+    assert_eq!(client.stat("current-connections").await, "1");
+    // The job isn't handed to the closed connection
+    assert_eq!(client.send("put 0 0 60 1\r\nx\r\n").await, "INSERTED 1");
+    assert_eq!(client.reserve("reserve-with-timeout 0\r\n").await, Ok(1));
+}
+
+#[tokio::test]
+async fn disconnect_after_sending_more() {
+    let addr = spawn_server().await;
+    let mut waiting = Client::connect(addr).await;
+    let mut client = Client::connect(addr).await;
+    waiting.write("reserve\r\n").await;
+    sleep(Duration::from_millis(100)).await;
+    waiting.write("list-tubes\r\n").await;
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(client.stat("current-waiting").await, "1");
+
+    drop(waiting);
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(client.stat("current-waiting").await, "0");
+    assert_eq!(client.stat("current-connections").await, "1");
+    assert_eq!(client.send("put 0 0 60 1\r\nx\r\n").await, "INSERTED 1");
+    assert_eq!(client.reserve("reserve-with-timeout 0\r\n").await, Ok(1));
+}
+
+#[tokio::test]
+async fn commands_after_a_blocked_reserve() {
+    let addr = spawn_server().await;
+    let mut worker = Client::connect(addr).await;
+    let mut producer = Client::connect(addr).await;
+    worker.write("reserve\r\nlist-tube-used\r\n").await;
+    sleep(Duration::from_millis(100)).await;
+    producer.send("put 0 0 60 1\r\nx\r\n").await;
+    assert_eq!(worker.read_line().await, "RESERVED 1 1");
+    assert_eq!(worker.read_line().await, "x");
+    assert_eq!(worker.read_line().await, "USING default");
+}